@@ -1,6 +1,12 @@
 use std::io;
+use std::fs::File;
+use std::path::Path;
 use digest::Digest;
 use hex_view::HexView;
+use md5::Md5;
+use rayon;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
 
 
 pub(crate) fn hasher<H: Digest, R: io::Read>(mut reader: R) -> io::Result<String> {
@@ -15,3 +21,29 @@ pub(crate) fn hasher<H: Digest, R: io::Read>(mut reader: R) -> io::Result<String
 
     Ok(format!("{:x}", HexView::from(hasher.result().as_slice())))
 }
+
+/// The digests that APT expects to find for each file that it fetches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checksums {
+    pub md5sum: String,
+    pub sha1: String,
+    pub sha256: String,
+    pub sha512: String,
+}
+
+/// Generates each of the digests of the file at the given path in parallel.
+pub(crate) fn checksums(path: &Path) -> io::Result<Checksums> {
+    // TODO: use bus_writer instead of reading the same file in each thread.
+    let ((sha1, sha256), (sha512, md5sum)) = rayon::join(
+        || rayon::join(
+            || File::open(path).and_then(hasher::<Sha1, File>),
+            || File::open(path).and_then(hasher::<Sha256, File>),
+        ),
+        || rayon::join(
+            || File::open(path).and_then(hasher::<Sha512, File>),
+            || File::open(path).and_then(hasher::<Md5, File>),
+        )
+    );
+
+    Ok(Checksums { md5sum: md5sum?, sha1: sha1?, sha256: sha256?, sha512: sha512? })
+}
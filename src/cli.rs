@@ -4,15 +4,25 @@ use clap::ArgMatches;
 #[derive(Debug, PartialEq)]
 pub enum Action<'a> {
     Build(Vec<&'a str>, bool),
+    Check,
     Clean,
-    Dist,
+    Dist(bool),
     Fetch(&'a str),
     FetchConfig,
+    List(Option<&'a str>, Option<&'a str>, Option<&'a str>, bool),
     Migrate(Vec<&'a str>, &'a str, &'a str),
     Pool,
+    RDepends(&'a str, bool),
+    Refresh,
     Remove(Vec<&'a str>),
+    Rollback,
+    Show(&'a str, bool),
+    SnapshotCreate(&'a str),
+    SnapshotList,
+    SnapshotPublish(&'a str),
     Update(&'a str, &'a str),
     UpdateRepository,
+    Verify,
 }
 
 impl<'a> Action<'a> {
@@ -23,9 +33,10 @@ impl<'a> Action<'a> {
                     Action::Build(pkgs.values_of("packages").unwrap().collect(), pkgs.is_present("force"))
                 }
                 ("pool", _) => Action::Pool,
-                ("dist", _) => Action::Dist,
+                ("dist", Some(dist)) => Action::Dist(dist.is_present("clear-cache")),
                 _ => Action::UpdateRepository
             }
+            ("check", _) => Action::Check,
             ("clean", _) => Action::Clean,
             ("config", Some(config)) => {
                 config.value_of("key").map_or(Action::FetchConfig, |key| {
@@ -34,9 +45,27 @@ impl<'a> Action<'a> {
                     })
                 })
             }
+            ("list", Some(list)) => Action::List(
+                list.value_of("component"),
+                list.value_of("arch"),
+                list.value_of("pattern"),
+                list.is_present("json")
+            ),
+            ("show", Some(show)) => Action::Show(show.value_of("package").unwrap(), show.is_present("json")),
+            ("rdepends", Some(rdepends)) => {
+                Action::RDepends(rdepends.value_of("package").unwrap(), rdepends.is_present("json"))
+            }
             ("remove", Some(pkgs)) => {
                 Action::Remove(pkgs.values_of("packages").unwrap().collect())
             }
+            ("refresh", _) => Action::Refresh,
+            ("rollback", _) => Action::Rollback,
+            ("snapshot", Some(snapshot)) => match snapshot.subcommand() {
+                ("create", Some(create)) => Action::SnapshotCreate(create.value_of("name").unwrap()),
+                ("publish", Some(publish)) => Action::SnapshotPublish(publish.value_of("name").unwrap()),
+                _ => Action::SnapshotList
+            }
+            ("verify", _) => Action::Verify,
             ("migrate", Some(migrate)) => {
                 Action::Migrate(
                     migrate.values_of("packages").unwrap().collect(),
@@ -1,58 +1,207 @@
 use bus_writer::BusWriter;
+use bzip2;
+use bzip2::write::BzEncoder;
 use deflate::Compression;
 use deflate::write::GzEncoder;
+use lz4_flex::frame::FrameEncoder;
 use xz2::write::XzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
 use std::io;
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::fs::File;
+use std::sync::Mutex;
 
-pub const UNCOMPRESSED: u8 = 1;
-pub const GZ_COMPRESS: u8 = 2;
-pub const XZ_COMPRESS: u8 = 4;
+/// A format which an index may be written in.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    None,
+    Gz,
+    Bz2,
+    Xz,
+    Zstd,
+    Lz4,
+}
+
+impl Codec {
+    /// The extension which is appended to the name of the index.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::None => "",
+            Codec::Gz => ".gz",
+            Codec::Bz2 => ".bz2",
+            Codec::Xz => ".xz",
+            Codec::Zstd => ".zst",
+            Codec::Lz4 => ".lz4",
+        }
+    }
+
+    /// The levels which the codec may be configured with, if it accepts a level at all.
+    pub fn levels(self) -> Option<RangeInclusive<u32>> {
+        match self {
+            Codec::None | Codec::Lz4 => None,
+            Codec::Gz | Codec::Bz2 => Some(1..=9),
+            Codec::Xz => Some(0..=9),
+            Codec::Zstd => Some(1..=22),
+        }
+    }
+}
+
+/// A format to write an index in, and the level to compress it with.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Format {
+    pub codec: Codec,
+    /// When unset, the highest level of the codec is used. Levels are rejected for uncompressed
+    /// and lz4 indexes, and gzip and bzip2 only distinguish between fast (1-3), default (4-6),
+    /// and best (7-9) compression.
+    pub level: Option<u32>,
+}
+
+impl Format {
+    pub fn new(codec: Codec) -> Self {
+        Format { codec, level: None }
+    }
+}
+
+/// Uncompressed, gzip, and xz: the formats which APT has supported for the longest.
+pub fn default_formats() -> Vec<Format> {
+    vec![Format::new(Codec::None), Format::new(Codec::Gz), Format::new(Codec::Xz)]
+}
 
 pub trait SyncWrite: Send + Sync + io::Write {}
 impl<T: Send + Sync + io::Write> SyncWrite for T {}
 
-pub fn compress<R: io::Read>(name: &str, path: &Path, stream: R, support: u8) -> io::Result<()> {
-    inner_compress(name, path, stream, support)
+/// A destination of the compressed stream, which must be finished after the stream has been
+/// written to it, so that the end of the stream is written and any error is reported.
+trait Destination: SyncWrite {
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+impl Destination for File {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Destination for GzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        GzEncoder::finish(*self).map(|_| ())
+    }
+}
+
+impl Destination for BzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        BzEncoder::finish(*self).map(|_| ())
+    }
+}
+
+impl Destination for XzEncoder<File> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        XzEncoder::finish(*self).map(|_| ())
+    }
+}
+
+impl Destination for Synced<FrameEncoder<File>> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        FrameEncoder::finish(self.into_inner())
+            .map(|_| ())
+            .map_err(|why| io::Error::new(io::ErrorKind::Other, format!("{}", why)))
+    }
+}
+
+impl Destination for Synced<ZstdEncoder<File>> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        ZstdEncoder::finish(self.into_inner()).map(|_| ())
+    }
+}
+
+/// The bus writer requires writers which are `Sync`, so encoders which are not are wrapped
+/// within an uncontended mutex.
+struct Synced<E>(Mutex<E>);
+
+impl<E> Synced<E> {
+    fn new(encoder: E) -> Self {
+        Synced(Mutex::new(encoder))
+    }
+
+    fn into_inner(self) -> E {
+        self.0.into_inner().unwrap()
+    }
+}
+
+impl<E: io::Write> io::Write for Synced<E> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.get_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.get_mut().unwrap().flush()
+    }
+}
+
+pub fn compress<R: io::Read>(name: &str, path: &Path, stream: R, formats: &[Format]) -> io::Result<()> {
+    inner_compress(name, path, stream, formats)
         .map_err(|why| io::Error::new(
             io::ErrorKind::Other,
             format!("failed to compress output to {} in {}: {}", name, path.display(), why)
         ))
 }
 
-fn inner_compress<R: io::Read>(name: &str, path: &Path, stream: R, support: u8) -> io::Result<()> {
-    if support == 0 {
+fn inner_compress<R: io::Read>(name: &str, path: &Path, stream: R, formats: &[Format]) -> io::Result<()> {
+    if formats.is_empty() {
         return Ok(());
     }
 
     let mut destinations = {
-        let mut writers: Vec<Box<SyncWrite>> = Vec::new();
-        if support & UNCOMPRESSED != 0 {
-            writers.push(Box::new(File::create(path.join(name))?));
-        }
+        let mut writers: Vec<Box<Destination>> = Vec::new();
+        for format in formats {
+            let file = File::create(path.join([name, format.codec.extension()].concat()))?;
+            let level = format.level;
+            writers.push(match format.codec {
+                Codec::None => Box::new(file),
+                Codec::Gz => {
+                    let level = match level.unwrap_or(9) {
+                        0..=3 => Compression::Fast,
+                        4..=6 => Compression::Default,
+                        _ => Compression::Best
+                    };
 
-        if support & GZ_COMPRESS != 0 {
-            let mut gz_file = File::create(path.join([name, ".gz"].concat()))?;
-            writers.push(Box::new(GzEncoder::new(gz_file, Compression::Best)));
-        }
+                    Box::new(GzEncoder::new(file, level))
+                }
+                Codec::Bz2 => {
+                    let level = match level.unwrap_or(9) {
+                        0..=3 => bzip2::Compression::Fastest,
+                        4..=6 => bzip2::Compression::Default,
+                        _ => bzip2::Compression::Best
+                    };
 
-        if support & XZ_COMPRESS != 0 {
-            let mut xz_file = File::create(path.join([name, ".xz"].concat()))?;
-            writers.push(Box::new(XzEncoder::new(xz_file, 9)));
+                    Box::new(BzEncoder::new(file, level))
+                }
+                Codec::Xz => Box::new(XzEncoder::new(file, level.unwrap_or(9))),
+                Codec::Zstd => {
+                    let encoder = ZstdEncoder::new(file, level.unwrap_or(19) as i32)?;
+                    Box::new(Synced::new(encoder))
+                }
+                Codec::Lz4 => Box::new(Synced::new(FrameEncoder::new(file))),
+            });
         }
 
         writers
     };
 
     info!(
-        "compressing {} to {}: uncompressed: {}, gzip: {}, xz: {}",
+        "compressing {} to {}: {:?}",
         name,
         path.display(),
-        support & UNCOMPRESSED != 0,
-        support & GZ_COMPRESS != 0,
-        support & XZ_COMPRESS != 0
+        formats.iter().map(|format| format.codec).collect::<Vec<Codec>>()
     );
 
-    BusWriter::new(stream, &mut destinations, |_| {}, || false).write()
-}
\ No newline at end of file
+    BusWriter::new(stream, &mut destinations, |_| {}, || false).write()?;
+
+    for destination in destinations {
+        destination.finish()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+/// Which `Packages` indexes list the packages of the `all` architecture.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchAll {
+    /// Only `binary-all/Packages`, which clients must be configured to fetch.
+    Separate,
+    /// Only the `Packages` index of each architecture, without a `binary-all` index.
+    Merge,
+    /// `binary-all/Packages` and the `Packages` index of each architecture, which is advertised
+    /// with `No-Support-for-Architecture-all: Packages`, as the Debian archive does.
+    Both,
+}
+
+impl ArchAll {
+    /// Whether `binary-all/Packages` is written, and `all` is listed as an architecture.
+    pub fn separate(self) -> bool {
+        self != ArchAll::Merge
+    }
+
+    /// Whether the packages are listed within the `Packages` index of each architecture.
+    pub fn merged(self) -> bool {
+        self != ArchAll::Separate
+    }
+}
+
+impl Default for ArchAll {
+    fn default() -> Self {
+        ArchAll::Separate
+    }
+}
@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+/// The backend which builds a source into packages.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Builder {
+    /// Builds within the sbuild chroot of the suite.
+    Sbuild,
+    /// Builds within a pbuilder chroot, via `pbuilder` or `pdebuild`.
+    Pbuilder {
+        /// The base tarball of the chroot. When unset, pbuilder's default is used.
+        basetgz: Option<PathBuf>,
+    },
+    /// Builds on the host with `dpkg-buildpackage`, which must already have the build
+    /// dependencies of the source installed.
+    Dpkg,
+    /// Builds within a container of the image, which should be of the suite being built.
+    Container {
+        image: String,
+        /// The container runtime, such as `podman` or `docker`. When unset, podman is used.
+        runtime: Option<String>,
+    },
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::Sbuild
+    }
+}
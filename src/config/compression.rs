@@ -0,0 +1,38 @@
+use compress::{default_formats, Codec, Format};
+
+/// The formats, and compression levels, that each kind of index is written in.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct Compression {
+    /// Uncompressed indexes are required to generate pdiffs.
+    #[serde(default = "default_formats")]
+    pub packages: Vec<Format>,
+    /// Uncompressed indexes are required to generate pdiffs.
+    #[serde(default = "default_formats")]
+    pub sources: Vec<Format>,
+    #[serde(default = "default_formats")]
+    pub contents: Vec<Format>,
+    #[serde(default = "default_translation_formats")]
+    pub translations: Vec<Format>,
+    #[serde(default = "default_appstream_formats")]
+    pub appstream: Vec<Format>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            packages: default_formats(),
+            sources: default_formats(),
+            contents: default_formats(),
+            translations: default_translation_formats(),
+            appstream: default_appstream_formats(),
+        }
+    }
+}
+
+fn default_translation_formats() -> Vec<Format> {
+    vec![Format::new(Codec::None), Format::new(Codec::Xz)]
+}
+
+fn default_appstream_formats() -> Vec<Format> {
+    vec![Format::new(Codec::Gz)]
+}
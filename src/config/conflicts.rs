@@ -0,0 +1,17 @@
+/// How files which are shipped by more than one package are handled.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileConflicts {
+    /// Conflicts are not checked for.
+    Ignore,
+    /// Each conflict is reported, but the dists are still published.
+    Warn,
+    /// Each conflict is reported, and the dists will not be published.
+    Error,
+}
+
+impl Default for FileConflicts {
+    fn default() -> Self {
+        FileConflicts::Warn
+    }
+}
@@ -0,0 +1,29 @@
+/// Where the `Contents` indexes of the suite are written.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentsLayout {
+    /// `Contents-<arch>` at the root of the dist directory, with each package qualified by its
+    /// component, unless the component is `main`.
+    Suite,
+    /// `<component>/Contents-<arch>` and `<component>/Contents-source`, which is the layout
+    /// of the Debian archive, and what modern versions of apt-file expect.
+    Component,
+    /// Both of the above layouts.
+    Both,
+}
+
+impl ContentsLayout {
+    pub fn suite(self) -> bool {
+        self != ContentsLayout::Component
+    }
+
+    pub fn component(self) -> bool {
+        self != ContentsLayout::Suite
+    }
+}
+
+impl Default for ContentsLayout {
+    fn default() -> Self {
+        ContentsLayout::Suite
+    }
+}
@@ -43,10 +43,12 @@ pub struct Direct {
     pub urls:      Vec<DirectPath>,
     pub checksum:  Option<String>,
     pub update:    Option<Update>,
+    /// Overrides the number of versions which the suite publishes of this package.
+    pub publish_versions: Option<usize>,
 }
 
 impl Direct {
-    pub fn get_destinations(&self, suite: &str, component: &str) -> io::Result<Vec<BinaryDestinations>> {
+    pub fn get_destinations(&self, suite: &str, component: &str, architectures: &[String]) -> io::Result<Vec<BinaryDestinations>> {
         let mut output = Vec::new();
 
         fn gen_filename(name: &str, version: &str, arch: &str, ext: &str) -> String {
@@ -86,7 +88,7 @@ impl Direct {
                 let extension = &file[ext_pos..];
                 let arch = match file_item.arch.as_ref() {
                     Some(ref arch) => arch.as_str(),
-                    None => misc::get_arch_from_stem(&file[..ext_pos - 1]),
+                    None => misc::get_arch_from_stem(&file[..ext_pos - 1], architectures),
                 };
 
                 let filename = gen_filename(name, &self.version, arch, extension);
@@ -0,0 +1,17 @@
+/// How packages whose dependencies cannot be satisfied are handled when the dists are generated.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Installability {
+    /// Installability is not checked for.
+    Ignore,
+    /// Each package that cannot be installed is reported, but the dists are still published.
+    Warn,
+    /// Each package that cannot be installed is reported, and the dists will not be published.
+    Error,
+}
+
+impl Default for Installability {
+    fn default() -> Self {
+        Installability::Ignore
+    }
+}
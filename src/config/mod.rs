@@ -1,18 +1,34 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::PathBuf;
 use std::ffi::OsStr;
 
 use toml::{self, de};
+use compress::Codec;
 use misc;
 
+mod arch_all;
+mod builder;
+mod compression;
+mod conflicts;
+mod contents;
 mod direct;
+mod installability;
 mod repos;
+mod signing;
 mod source;
 
+pub use self::arch_all::*;
+pub use self::builder::*;
+pub use self::compression::*;
+pub use self::conflicts::*;
+pub use self::contents::*;
 pub use self::direct::*;
+pub use self::installability::*;
 pub use self::repos::*;
+pub use self::signing::*;
 pub use self::source::*;
 
 #[derive(Debug, Fail)]
@@ -28,7 +44,17 @@ pub enum ParsingError {
     #[fail(display = "source URL and path defined for {}. Only one should be defined.", source)]
     SourcePathAndUrlDefined { source: String },
     #[fail(display = "neither a URL or path was defined for the source named {}", source)]
-    SourceNotDefined { source: String }
+    SourceNotDefined { source: String },
+    #[fail(display = "'{}' is not a valid architecture name", arch)]
+    InvalidArchitecture { arch: String },
+    #[fail(display = "'{}' is not a valid suite alias", alias)]
+    InvalidAlias { alias: String },
+    #[fail(display = "but_automatic_upgrades requires not_automatic to be set")]
+    ButAutomaticUpgrades,
+    #[fail(display = "{:?} compression of {} does not support level {}", codec, index, level)]
+    InvalidCompressionLevel { index: &'static str, codec: Codec, level: u32 },
+    #[fail(display = "pdiffs require {} indexes to be written uncompressed", index)]
+    PdiffsRequireUncompressed { index: &'static str },
 }
 
 #[derive(Debug, Fail)]
@@ -42,13 +68,34 @@ pub enum ConfigError {
 pub struct Config {
     #[serde(skip)]
     pub path: PathBuf,
+    /// The codename of the suite, which is also the name of its pool and dists.
     pub archive: String,
+    /// The suite name which is published alongside the codename, such as `stable` or `proposed`.
+    /// When unset, the codename is used.
+    pub suite: Option<String>,
+    /// Additional names which the dists are linked to, within `dists/`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Marks the suite with `NotAutomatic: yes`, so that APT never installs from it unless asked.
+    #[serde(default)]
+    pub not_automatic: bool,
+    /// Marks the suite with `ButAutomaticUpgrades: yes`, so that installed packages are still
+    /// upgraded from it. Only meaningful alongside `not_automatic`.
+    #[serde(default)]
+    pub but_automatic_upgrades: bool,
     pub version: String,
     pub origin: String,
     pub label: String,
     pub email: String,
+    /// The architectures that packages are published for, besides `all`.
     #[serde(default = "default_architectures")]
     pub architectures: Vec<String>,
+    /// Which `Packages` indexes the packages of the `all` architecture are listed in.
+    #[serde(default)]
+    pub arch_all: ArchAll,
+    /// The number of sources which may be built at the same time, once the sources that they
+    /// depend upon have been built. When unset, one source is built at a time.
+    pub build_jobs: Option<usize>,
     /// Packages which are already in the deb format.
     pub direct: Option<Vec<Direct>>,
     /// Projects which can be built from source.
@@ -58,6 +105,46 @@ pub struct Config {
     #[serde(default = "default_component")]
     pub default_component: String,
     pub extra_repos: Option<Vec<String>>,
+    /// The number of versions of each package which are published in the `Packages` indexes.
+    /// Packages may override this individually. When unset, only the newest version is published.
+    pub publish_versions: Option<usize>,
+    /// When set, indexes are also published under `by-hash/SHA256/`, keeping this many generations.
+    pub by_hash: Option<usize>,
+    /// When set, ed-style patches are generated for the `Packages` and `Sources` indexes,
+    /// keeping this many patches in the history of each index.
+    pub pdiffs: Option<usize>,
+    /// When set, the `Release` file expires this many days after it was signed, via the
+    /// `Valid-Until` field. The release must then be refreshed with `debrep refresh`.
+    pub valid_for: Option<u32>,
+    /// When enabled, the long descriptions of packages are written to the `i18n/Translation-en`
+    /// index of each component, and are referenced from `Packages` by their `Description-md5`.
+    #[serde(default)]
+    pub translations: bool,
+    /// When enabled, the AppStream metadata and icons of each component are written to its
+    /// `dep11/` directory.
+    #[serde(default)]
+    pub appstream: bool,
+    /// Where the `Contents` indexes are written.
+    #[serde(default)]
+    pub contents_layout: ContentsLayout,
+    /// Whether files which are shipped by more than one package are reported, or are an error.
+    #[serde(default)]
+    pub file_conflicts: FileConflicts,
+    /// Whether packages whose dependencies cannot be satisfied are reported, or are an error.
+    #[serde(default)]
+    pub installability: Installability,
+    /// `Packages` indexes of the distribution that the suite is installed upon, which may
+    /// satisfy the dependencies of its packages. Paths are relative to the root of the project.
+    #[serde(default)]
+    pub upstream_packages: Vec<PathBuf>,
+    /// How the `Release` file is signed. When unset, gpg signs with the key of the `email`.
+    pub signing: Option<Signing>,
+    /// The formats that each kind of index is written in.
+    #[serde(default)]
+    pub compression: Compression,
+    /// Components which are published for different architectures than the suite's.
+    #[serde(default)]
+    pub component_architectures: HashMap<String, Vec<String>>,
     #[serde(skip)]
     pub extra_keys: Vec<PathBuf>,
 }
@@ -92,10 +179,145 @@ impl Config {
     pub fn package_exists(&self, filename: &str) -> bool {
         self.direct_exists(filename) || self.source_exists(filename)
     }
+
+    /// The name of the suite, which is the codename unless a suite name was given.
+    pub fn suite_name(&self) -> &str {
+        self.suite.as_ref().unwrap_or(&self.archive)
+    }
+
+    /// The names that the dists are linked to, besides the codename.
+    pub fn dist_links(&self) -> Vec<&str> {
+        let mut links = Vec::new();
+        for name in self.suite.iter().chain(self.aliases.iter()) {
+            if name != &self.archive && !links.contains(&name.as_str()) {
+                links.push(name.as_str());
+            }
+        }
+
+        links
+    }
+
+    /// Ensures that each alias may be used as the name of a directory within `dists/`.
+    fn validate_aliases(&self) -> Result<(), ParsingError> {
+        for alias in self.suite.iter().chain(self.aliases.iter()) {
+            if alias.is_empty() || alias.starts_with('.') || alias.contains('/') {
+                return Err(ParsingError::InvalidAlias { alias: alias.clone() });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `ButAutomaticUpgrades` only has a meaning for suites which are `NotAutomatic`.
+    fn validate_automatic(&self) -> Result<(), ParsingError> {
+        if self.but_automatic_upgrades && !self.not_automatic {
+            Err(ParsingError::ButAutomaticUpgrades)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Ensures that the level of each compression format is within the range of its codec, and
+    /// that the indexes which pdiffs are generated from are written uncompressed.
+    fn validate_compression(&self) -> Result<(), ParsingError> {
+        let compression = &self.compression;
+        if self.pdiffs.is_some() {
+            for &(index, formats) in &[("Packages", &compression.packages), ("Sources", &compression.sources)] {
+                if !formats.iter().any(|format| format.codec == Codec::None) {
+                    return Err(ParsingError::PdiffsRequireUncompressed { index });
+                }
+            }
+        }
+
+        let indexes = [
+            ("Packages", &compression.packages),
+            ("Sources", &compression.sources),
+            ("Contents", &compression.contents),
+            ("Translation", &compression.translations),
+            ("AppStream", &compression.appstream),
+        ];
+
+        for &(index, formats) in &indexes {
+            for format in formats.iter() {
+                if let Some(level) = format.level {
+                    if !format.codec.levels().map_or(false, |levels| levels.contains(&level)) {
+                        return Err(ParsingError::InvalidCompressionLevel { index, codec: format.codec, level });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of versions of the named package which are published, which is either the
+    /// value of the package, of the source that it was built from, or of the suite.
+    ///
+    /// The source of a binary package is given by the `Source` field of its control file, and
+    /// is the name of the package when the field is omitted.
+    pub fn publish_versions_of(&self, package: &str, source: Option<&str>) -> usize {
+        let source_name = source.unwrap_or(package);
+        let direct = self.direct.as_ref().and_then(|packages| {
+            packages.iter()
+                .find(|direct| direct.name == package
+                    || direct.urls.iter().any(|url| url.name.as_ref().map_or(false, |name| name == package)))
+                .and_then(|direct| direct.publish_versions)
+        });
+
+        let source = || self.source.as_ref().and_then(|sources| {
+            sources.iter()
+                .find(|source| source.name == source_name)
+                .and_then(|source| source.publish_versions)
+        });
+
+        direct.or_else(source).or(self.publish_versions).unwrap_or(1).max(1)
+    }
+
+    /// Whether any source overrides the number of versions which are published.
+    pub fn has_source_publish_versions(&self) -> bool {
+        self.source.as_ref()
+            .map_or(false, |sources| sources.iter().any(|source| source.publish_versions.is_some()))
+    }
+
+    /// The architectures that packages of the component are published for, besides `all`.
+    pub fn architectures_of(&self, component: &str) -> &[String] {
+        self.component_architectures.get(component).unwrap_or(&self.architectures)
+    }
+
+    /// Every architecture which any component is published for, besides `all`, in sorted order.
+    pub fn all_architectures(&self) -> Vec<String> {
+        let mut architectures = self.architectures.iter()
+            .chain(self.component_architectures.values().flat_map(|archs| archs.iter()))
+            .cloned()
+            .collect::<Vec<String>>();
+
+        architectures.sort();
+        architectures.dedup();
+        architectures
+    }
+
+    /// Ensures that each architecture is a valid Debian architecture name.
+    fn validate_architectures(&self) -> Result<(), ParsingError> {
+        let architectures = self.architectures.iter()
+            .chain(self.component_architectures.values().flat_map(|archs| archs.iter()));
+
+        for arch in architectures {
+            let valid = !arch.is_empty()
+                && !["all", "any", "source"].contains(&arch.as_str())
+                && !arch.starts_with('-')
+                && arch.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+
+            if !valid {
+                return Err(ParsingError::InvalidArchitecture { arch: arch.clone() });
+            }
+        }
+
+        Ok(())
+    }
 }
 
 fn default_architectures() -> Vec<String> {
-    vec!["amd64".into(), "i368".into()]
+    vec!["amd64".into(), "i386".into()]
 }
 fn default_component() -> String { "main".into() }
 
@@ -204,6 +426,10 @@ pub fn parse(path: PathBuf) -> Result<Config, ParsingError> {
             toml::from_slice(&buffer).map_err(|why| ParsingError::Toml { file: path.clone(), why })
         })?;
 
+    config.validate_architectures()?;
+    config.validate_aliases()?;
+    config.validate_automatic()?;
+    config.validate_compression()?;
     config.path = path;
     if let Ok(key_dir) = fs::read_dir("keys") {
         for key in key_dir.flat_map(|x| x.ok()) {
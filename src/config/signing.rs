@@ -0,0 +1,19 @@
+use std::path::PathBuf;
+
+/// The backend which signs the `Release` file of the suite.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Signing {
+    /// Signs with `gpg`, using each of the given keys.
+    Gpg {
+        /// The GnuPG home directory which contains the keys. When unset, gpg's default is used.
+        homedir: Option<PathBuf>,
+        /// Fingerprints of the keys to sign with. When empty, the `email` of the config is used.
+        #[serde(default)]
+        keys: Vec<String>,
+    },
+    /// Signs with a native OpenPGP implementation, using the armored secret key in each file.
+    Native {
+        keys: Vec<PathBuf>,
+    },
+}
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use super::Builder;
 
 // Files that we want to cache and re-use between runs. These files will be symlinked.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -47,6 +48,12 @@ pub struct Source {
     pub retain:           usize,
     #[serde(default = "default_requires_extract")]
     pub extract: bool,
+    /// Overrides the number of versions which the suite publishes of each binary package that
+    /// was built from this source.
+    pub publish_versions: Option<usize>,
+    /// The backend which builds the source. When unset, sbuild is used.
+    #[serde(default)]
+    pub builder: Builder,
 }
 
 fn default_build_source() -> bool { true }
@@ -0,0 +1,128 @@
+use super::Control;
+
+const SIGNED_HEADER: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+const SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// Parses each paragraph of a deb822-formatted document into a control map.
+///
+/// Continuation lines of multi-line fields are kept verbatim, and joined to the first line of
+/// the value with a newline, so that `value.lines().next()` yields the first line.
+pub fn parse(input: &str) -> Vec<Control> {
+    let mut paragraphs = Vec::new();
+    let mut paragraph = Control::new();
+    let mut current: Option<(String, String)> = None;
+
+    for line in strip_signature(input).lines() {
+        if line.trim().is_empty() {
+            if let Some((key, value)) = current.take() {
+                paragraph.insert(key, value);
+            }
+
+            if !paragraph.is_empty() {
+                paragraphs.push(paragraph);
+                paragraph = Control::new();
+            }
+        } else if line.starts_with('#') {
+            continue
+        } else if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, ref mut value)) = current {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if let Some(pos) = line.find(':') {
+            if let Some((key, value)) = current.take() {
+                paragraph.insert(key, value);
+            }
+
+            let (key, value) = line.split_at(pos);
+            current = Some((key.trim().to_owned(), value[1..].trim().to_owned()));
+        }
+    }
+
+    if let Some((key, value)) = current.take() {
+        paragraph.insert(key, value);
+    }
+
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+
+    paragraphs
+}
+
+/// Strips the OpenPGP armor from a clearsigned document, such as a `.dsc` or `InRelease` file.
+///
+/// Documents which are not signed are returned as they are.
+pub fn strip_signature(input: &str) -> String {
+    let mut lines = input.lines();
+    if lines.next().map_or(true, |line| line.trim_end() != SIGNED_HEADER) {
+        return input.to_owned();
+    }
+
+    // Skip the armor headers, which are terminated by an empty line.
+    for line in &mut lines {
+        if line.trim().is_empty() {
+            break
+        }
+    }
+
+    let mut output = String::with_capacity(input.len());
+    for line in lines {
+        if line.trim_end() == SIGNATURE_HEADER {
+            break
+        }
+
+        // Lines which begin with a dash are dash-escaped by the signer.
+        output.push_str(if line.starts_with("- ") { &line[2..] } else { line });
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_dsc() {
+        const DSC: &str = r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Format: 3.0 (native)
+Source: hello
+Binary: hello
+Version: 1.0
+Files:
+ d41d8cd98f00b204e9800998ecf8427e 0 hello_1.0.tar.xz
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCAAdFiEE
+-----END PGP SIGNATURE-----
+"#;
+
+        let paragraphs = parse(DSC);
+        assert_eq!(paragraphs.len(), 1);
+
+        let dsc = &paragraphs[0];
+        assert_eq!(dsc.get("Format").map(|x| x.as_str()), Some("3.0 (native)"));
+        assert_eq!(dsc.get("Source").map(|x| x.as_str()), Some("hello"));
+        assert_eq!(
+            dsc.get("Files").map(|x| x.as_str()),
+            Some("\n d41d8cd98f00b204e9800998ecf8427e 0 hello_1.0.tar.xz")
+        );
+    }
+
+    #[test]
+    fn multiple_paragraphs() {
+        const PACKAGES: &str = "Package: a\nDescription: short\n long\n .\n more\n\nPackage: b\n";
+
+        let paragraphs = parse(PACKAGES);
+        assert_eq!(paragraphs.len(), 2);
+        assert_eq!(
+            paragraphs[0].get("Description").map(|x| x.as_str()),
+            Some("short\n long\n .\n more")
+        );
+        assert_eq!(paragraphs[1].get("Package").map(|x| x.as_str()), Some("b"));
+    }
+}
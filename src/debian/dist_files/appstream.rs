@@ -0,0 +1,527 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// The sizes of the icons which are cached within the `icons-<size>x<size>.tar.gz` archives.
+pub const ICON_SIZES: &[u32] = &[48, 64, 128];
+
+/// The AppStream components of a package, and the icons which they reference.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AppStreamEntry {
+    pub components: Vec<AppStreamComponent>,
+    pub icons: Vec<AppStreamIcon>,
+}
+
+/// An icon of a component, as it is stored within the icon archive of its size.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AppStreamIcon {
+    pub size: u32,
+    /// The name of the icon within the archive, as `<package>_<icon>.png`.
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// A component from the metainfo of a package, as it is written to `Components-<arch>.yml`.
+///
+/// Translatable values are keyed by their locale, where `C` is the untranslated value.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AppStreamComponent {
+    pub kind: String,
+    pub id: String,
+    pub package: String,
+    pub name: BTreeMap<String, String>,
+    pub summary: BTreeMap<String, String>,
+    /// The untranslated description, as AppStream markup.
+    pub description: Option<String>,
+    pub developer_name: Option<String>,
+    pub project_license: Option<String>,
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    pub urls: BTreeMap<String, String>,
+    /// The IDs of the desktop entries which launch the component.
+    pub launchables: Vec<String>,
+    pub stock_icon: Option<String>,
+    /// The sizes and names of the icons which were cached for this component.
+    pub cached_icons: Vec<(u32, String)>,
+}
+
+impl AppStreamComponent {
+    /// Generates the YAML document of this component, for the `Components-<arch>.yml` index.
+    pub fn generate_entry(&self) -> Vec<u8> {
+        let mut output = String::with_capacity(1024);
+        output.push_str("---\n");
+        push_value(&mut output, "Type", &self.kind);
+        push_value(&mut output, "ID", &self.id);
+        push_value(&mut output, "Package", &self.package);
+        push_translated(&mut output, "Name", self.name.iter().map(|(lang, name)| (lang.as_str(), name)));
+        push_translated(&mut output, "Summary", self.summary.iter().map(|(lang, summary)| (lang.as_str(), summary)));
+
+        if let Some(ref description) = self.description {
+            push_translated(&mut output, "Description", Some(("C", description)).into_iter());
+        }
+
+        if let Some(ref developer_name) = self.developer_name {
+            push_translated(&mut output, "DeveloperName", Some(("C", developer_name)).into_iter());
+        }
+
+        if let Some(ref license) = self.project_license {
+            push_value(&mut output, "ProjectLicense", license);
+        }
+
+        push_list(&mut output, "Categories", "", &self.categories);
+
+        if !self.keywords.is_empty() {
+            output.push_str("Keywords:\n");
+            push_list(&mut output, "C", "  ", &self.keywords);
+        }
+
+        if !self.urls.is_empty() {
+            output.push_str("Url:\n");
+            for (kind, url) in &self.urls {
+                output.push_str(&["  ", kind, ": ", &quote(url), "\n"].concat());
+            }
+        }
+
+        if !self.launchables.is_empty() {
+            output.push_str("Launchable:\n");
+            push_list(&mut output, "desktop-id", "  ", &self.launchables);
+        }
+
+        if self.stock_icon.is_some() || !self.cached_icons.is_empty() {
+            output.push_str("Icon:\n");
+            if let Some(ref stock) = self.stock_icon {
+                output.push_str(&["  stock: ", &quote(stock), "\n"].concat());
+            }
+
+            if !self.cached_icons.is_empty() {
+                output.push_str("  cached:\n");
+                for &(size, ref name) in &self.cached_icons {
+                    let size = size.to_string();
+                    output.push_str(&["  - name: ", &quote(name), "\n"].concat());
+                    output.push_str(&["    width: ", &size, "\n    height: ", &size, "\n"].concat());
+                }
+            }
+        }
+
+        output.into_bytes()
+    }
+}
+
+/// Generates the header document of a `Components-<arch>.yml` index.
+pub fn appstream_header(origin: &str) -> Vec<u8> {
+    ["---\nFile: DEP-11\nVersion: '0.12'\nOrigin: ", &quote(origin), "\n"].concat().into_bytes()
+}
+
+/// The files of a package which AppStream components are generated from, which are collected
+/// while the data archive of the package is read.
+#[derive(Default)]
+pub struct AppStreamFiles {
+    metainfo: Vec<(String, String)>,
+    desktop_entries: HashMap<String, String>,
+    icons: HashMap<(u32, String), Vec<u8>>,
+}
+
+impl AppStreamFiles {
+    /// Reads the file from the data archive if it is metainfo, a desktop entry, or an icon.
+    pub fn collect<R: Read>(&mut self, path: &Path, file: &mut R) -> io::Result<()> {
+        let path = path.strip_prefix(".").unwrap_or(path);
+        let (parent, name) = match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => return Ok(())
+        };
+
+        if (parent == Path::new("usr/share/metainfo") || parent == Path::new("usr/share/appdata"))
+            && name.ends_with(".xml")
+        {
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            self.metainfo.push((name.to_owned(), data));
+        } else if parent == Path::new("usr/share/applications") && name.ends_with(".desktop") {
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            self.desktop_entries.insert(name.to_owned(), data);
+        } else if name.ends_with(".png") {
+            if let Some(size) = icon_size(parent) {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                self.icons.insert((size, name[..name.len() - 4].to_owned()), data);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the metainfo of the package, returning nothing if the package has no components.
+    ///
+    /// Invalid metainfo is reported and skipped, rather than failing the generation of the dists.
+    pub fn into_entry(self, package: &str) -> Option<AppStreamEntry> {
+        let mut entry = AppStreamEntry { components: Vec::new(), icons: Vec::new() };
+
+        for &(ref file, ref data) in &self.metainfo {
+            let mut component = match parse_metainfo(package, data) {
+                Ok(component) => component,
+                Err(why) => {
+                    warn!("{}: skipping invalid metainfo in {}: {}", package, file, why);
+                    continue
+                }
+            };
+
+            let desktop_entry = component.launchables.iter()
+                .filter_map(|id| self.desktop_entries.get(id))
+                .map(|data| parse_desktop_entry(data))
+                .next();
+
+            if let Some(desktop_entry) = desktop_entry {
+                if component.stock_icon.is_none() {
+                    component.stock_icon = desktop_entry.get("Icon").cloned();
+                }
+
+                if component.categories.is_empty() {
+                    if let Some(categories) = desktop_entry.get("Categories") {
+                        component.categories = categories.split(';')
+                            .filter(|category| !category.is_empty())
+                            .map(String::from)
+                            .collect();
+                    }
+                }
+            }
+
+            // Icons referenced by an absolute path are not themed, and are left to the client.
+            let icon = component.stock_icon.clone().filter(|icon| !icon.contains('/'));
+            if let Some(icon) = icon {
+                let icon = icon.trim_end_matches(".png");
+                for &size in ICON_SIZES {
+                    if let Some(data) = self.icons.get(&(size, icon.to_owned())) {
+                        let name = [package, "_", icon, ".png"].concat();
+                        component.cached_icons.push((size, name.clone()));
+                        entry.icons.push(AppStreamIcon { size, name, data: data.clone() });
+                    }
+                }
+            }
+
+            entry.components.push(component);
+        }
+
+        if entry.components.is_empty() { None } else { Some(entry) }
+    }
+}
+
+/// The size of the icons within a `usr/share/icons/hicolor/<size>x<size>/apps` directory.
+fn icon_size(directory: &Path) -> Option<u32> {
+    let directory = directory.strip_prefix("usr/share/icons/hicolor").ok()?;
+    let mut components = directory.iter().map(|component| component.to_str());
+    let size = components.next()??;
+    if components.next()?? != "apps" || components.next().is_some() {
+        return None;
+    }
+
+    let mut dimensions = size.split('x').map(|value| value.parse::<u32>().ok());
+    match (dimensions.next()?, dimensions.next()?, dimensions.next()) {
+        (Some(width), Some(height), None) if width == height && ICON_SIZES.contains(&width) => Some(width),
+        _ => None
+    }
+}
+
+/// Parses the keys of the `[Desktop Entry]` group of a desktop entry.
+fn parse_desktop_entry(data: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut in_group = false;
+    for line in data.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+        } else if in_group && !line.starts_with('#') {
+            if let Some(position) = line.find('=') {
+                fields.insert(line[..position].trim().to_owned(), line[position + 1..].trim().to_owned());
+            }
+        }
+    }
+
+    fields
+}
+
+/// An element of the metainfo which has not yet been closed.
+struct Element {
+    name: String,
+    lang: Option<String>,
+    kind: Option<String>,
+}
+
+/// Parses the metainfo XML of a component.
+///
+/// Only the untranslated description is kept, as translations of it are distributed separately.
+fn parse_metainfo(package: &str, xml: &str) -> Result<AppStreamComponent, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut buffer = Vec::new();
+    let mut component = AppStreamComponent { package: package.to_owned(), ..Default::default() };
+    let mut stack: Vec<Element> = Vec::new();
+    let mut text = String::new();
+    let mut description = String::new();
+    // The depth of a translated element within the description, which is skipped.
+    let mut skip_depth: Option<usize> = None;
+
+    loop {
+        buffer.clear();
+        match reader.read_event(&mut buffer).map_err(|why| why.to_string())? {
+            Event::Start(ref start) => {
+                let element = element(&reader, start)?;
+                if stack.is_empty() {
+                    if element.name != "component" && element.name != "application" {
+                        return Err(format!("unexpected root element: {}", element.name));
+                    }
+
+                    component.kind = match element.kind.as_ref().map(String::as_str) {
+                        Some("desktop") | Some("desktop-application") => "desktop-application".into(),
+                        Some(kind) => kind.to_owned(),
+                        None if element.name == "application" => "desktop-application".into(),
+                        None => "generic".into(),
+                    };
+                } else if in_description(&stack) && skip_depth.is_none() {
+                    if element.lang.is_some() {
+                        skip_depth = Some(stack.len());
+                    } else {
+                        description_tag(&mut description, &element.name, false);
+                    }
+                }
+
+                text.clear();
+                stack.push(element);
+            }
+            Event::Text(ref value) => {
+                let value = value.unescape_and_decode(&reader).map_err(|why| why.to_string())?;
+                if in_description(&stack) {
+                    if skip_depth.is_none() {
+                        let value = if ends_with_block(&description) { value.trim_start() } else { &value };
+                        description.push_str(&escape(value));
+                    }
+                } else {
+                    text.push_str(&value);
+                }
+            }
+            Event::End(_) => {
+                let element = stack.pop().ok_or_else(|| "unbalanced closing element".to_owned())?;
+                if skip_depth == Some(stack.len()) {
+                    skip_depth = None;
+                    continue
+                }
+
+                if in_description(&stack) {
+                    if skip_depth.is_none() {
+                        description_tag(&mut description, &element.name, true);
+                    }
+                    continue
+                }
+
+                let value = collapse_whitespace(&text);
+                text.clear();
+                if stack.len() == 1 {
+                    let lang = element.lang.clone().unwrap_or_else(|| "C".into());
+                    match element.name.as_str() {
+                        "id" => component.id = value,
+                        "name" => { component.name.insert(lang, value); }
+                        "summary" => { component.summary.insert(lang, value); }
+                        "developer_name" if element.lang.is_none() => component.developer_name = Some(value),
+                        "project_license" => component.project_license = Some(value),
+                        "description" => {
+                            let value = collapse_whitespace(&description);
+                            if !value.is_empty() {
+                                component.description = Some(value);
+                            }
+                        }
+                        "url" => {
+                            let kind = element.kind.unwrap_or_else(|| "homepage".into());
+                            component.urls.insert(kind, value);
+                        }
+                        "launchable" if element.kind.as_ref().map_or(false, |kind| kind == "desktop-id") => {
+                            component.launchables.push(value);
+                        }
+                        "icon" if element.kind.as_ref().map_or(false, |kind| kind == "stock") => {
+                            component.stock_icon = Some(value);
+                        }
+                        _ => ()
+                    }
+                } else if stack.len() == 2 {
+                    match (stack[1].name.as_str(), element.name.as_str()) {
+                        ("categories", "category") => component.categories.push(value),
+                        ("keywords", "keyword") if element.lang.is_none() => component.keywords.push(value),
+                        _ => ()
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => ()
+        }
+    }
+
+    if component.id.is_empty() {
+        return Err("the component has no ID".into());
+    }
+
+    if !component.name.contains_key("C") || !component.summary.contains_key("C") {
+        return Err(format!("{} has no untranslated name or summary", component.id));
+    }
+
+    // Legacy metainfo is identified by the desktop entry which launches it.
+    if component.launchables.is_empty() && component.id.ends_with(".desktop") {
+        component.launchables.push(component.id.clone());
+    }
+
+    Ok(component)
+}
+
+fn element<B>(reader: &Reader<B>, start: &BytesStart) -> Result<Element, String> {
+    let mut element = Element {
+        name: String::from_utf8_lossy(start.name()).into_owned(),
+        lang: None,
+        kind: None
+    };
+
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|why| why.to_string())?;
+        let value = attribute.unescape_and_decode_value(reader).map_err(|why| why.to_string())?;
+        match attribute.key {
+            b"xml:lang" => element.lang = Some(value),
+            b"type" => element.kind = Some(value),
+            _ => ()
+        }
+    }
+
+    Ok(element)
+}
+
+fn in_description(stack: &[Element]) -> bool {
+    stack.len() > 1 && stack[1].name == "description"
+}
+
+/// Writes the markup of a description element, which is trimmed of the whitespace around it.
+fn description_tag(description: &mut String, name: &str, closing: bool) {
+    match name {
+        "p" | "ul" | "ol" | "li" => {
+            let trimmed = description.trim_end().len();
+            description.truncate(trimmed);
+        }
+        "em" | "code" => (),
+        _ => return
+    }
+
+    description.push_str(&[if closing { "</" } else { "<" }, name, ">"].concat());
+}
+
+/// Whether the description is empty, or ends with a tag around which whitespace is trimmed.
+fn ends_with_block(description: &str) -> bool {
+    description.is_empty() || ["p>", "ul>", "ol>", "li>"].iter().any(|tag| {
+        description.ends_with(&["<", tag].concat()) || description.ends_with(&["</", tag].concat())
+    })
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Quotes a YAML scalar, so that its value is never interpreted as another type.
+fn quote(value: &str) -> String {
+    ["'", &value.replace('\'', "''"), "'"].concat()
+}
+
+fn push_value(output: &mut String, key: &str, value: &str) {
+    output.push_str(&[key, ": ", &quote(value), "\n"].concat());
+}
+
+fn push_translated<'a, I: Iterator<Item = (&'a str, &'a String)>>(output: &mut String, key: &str, values: I) {
+    output.push_str(&[key, ":\n"].concat());
+    for (lang, value) in values {
+        output.push_str(&["  ", lang, ": ", &quote(value), "\n"].concat());
+    }
+}
+
+fn push_list(output: &mut String, key: &str, indent: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+
+    output.push_str(&[indent, key, ":\n"].concat());
+    for value in values {
+        output.push_str(&[indent, "- ", &quote(value), "\n"].concat());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METAINFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<component type="desktop-application">
+  <id>com.system76.Example</id>
+  <name>Example</name>
+  <name xml:lang="de">Beispiel</name>
+  <summary>An example &amp; nothing more</summary>
+  <description>
+    <p>
+      Does <em>nothing</em>.
+    </p>
+    <p xml:lang="de">Tut nichts.</p>
+    <ul>
+      <li>Fast</li>
+    </ul>
+  </description>
+  <launchable type="desktop-id">com.system76.Example.desktop</launchable>
+  <url type="homepage">https://system76.com</url>
+</component>
+"#;
+
+    #[test]
+    fn appstream_component() {
+        let mut files = AppStreamFiles::default();
+        files.metainfo.push(("com.system76.Example.metainfo.xml".into(), METAINFO.into()));
+        files.desktop_entries.insert(
+            "com.system76.Example.desktop".into(),
+            "[Desktop Entry]\nName=Example\nIcon=example\nCategories=Utility;GTK;\n".into()
+        );
+        files.icons.insert((64, "example".into()), vec![1, 2, 3]);
+
+        let entry = files.into_entry("example").unwrap();
+        assert_eq!(entry.icons, vec![AppStreamIcon { size: 64, name: "example_example.png".into(), data: vec![1, 2, 3] }]);
+        assert_eq!(
+            String::from_utf8(entry.components[0].generate_entry()).unwrap(),
+            r#"---
+Type: 'desktop-application'
+ID: 'com.system76.Example'
+Package: 'example'
+Name:
+  C: 'Example'
+  de: 'Beispiel'
+Summary:
+  C: 'An example & nothing more'
+Description:
+  C: '<p>Does <em>nothing</em>.</p><ul><li>Fast</li></ul>'
+Categories:
+- 'Utility'
+- 'GTK'
+Url:
+  homepage: 'https://system76.com'
+Launchable:
+  desktop-id:
+  - 'com.system76.Example.desktop'
+Icon:
+  stock: 'example'
+  cached:
+  - name: 'example_example.png'
+    width: 64
+    height: 64
+"#
+        );
+    }
+
+    #[test]
+    fn hicolor_icon_sizes() {
+        assert_eq!(icon_size(Path::new("usr/share/icons/hicolor/64x64/apps")), Some(64));
+        assert_eq!(icon_size(Path::new("usr/share/icons/hicolor/64x64@2/apps")), None);
+        assert_eq!(icon_size(Path::new("usr/share/icons/hicolor/32x32/apps")), None);
+        assert_eq!(icon_size(Path::new("usr/share/icons/Pop/64x64/apps")), None);
+    }
+}
@@ -0,0 +1,181 @@
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use super::super::{Arch, Entries};
+
+/// A file which is shipped by two packages of an architecture, where neither package declares
+/// that it replaces, or conflicts with, the other. Installing both will fail.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileConflict {
+    pub arch: Arch,
+    pub path: PathBuf,
+    pub packages: (String, String),
+}
+
+impl fmt::Display for FileConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: /{} is shipped by both {} and {}, which neither replaces nor conflicts with the other",
+            self.arch,
+            self.path.display(),
+            self.packages.0,
+            self.packages.1
+        )
+    }
+}
+
+/// Finds the files of each architecture which are shipped by more than one package.
+///
+/// Packages of the `all` architecture are checked against the packages of every architecture.
+pub fn file_conflicts(entries: &Entries) -> Vec<FileConflict> {
+    let all = entries.get("all");
+    let archs = entries.keys()
+        .filter(|&arch| arch != "all" || entries.len() == 1)
+        .collect::<Vec<&Arch>>();
+
+    let mut conflicts = archs.into_par_iter()
+        .flat_map(|arch| {
+            let mut arch_entries = vec![&entries[arch]];
+            if arch != "all" {
+                arch_entries.extend(all);
+            }
+
+            // The names of the packages that each package replaces or conflicts with.
+            let mut relations: HashMap<&str, HashSet<&str>> = HashMap::new();
+            for &&(ref components, _) in &arch_entries {
+                for package in components.values().flat_map(|packages| packages.iter()) {
+                    if let Some(name) = package.control.get("Package") {
+                        let names = relations.entry(name.as_str()).or_insert_with(HashSet::new);
+                        for field in &["Replaces", "Conflicts"] {
+                            if let Some(value) = package.control.get(*field) {
+                                names.extend(relation_names(value));
+                            }
+                        }
+                    }
+                }
+            }
+
+            // The names of the packages that ship each path.
+            let mut owners: HashMap<&Path, Vec<&str>> = HashMap::new();
+            for &&(_, ref contents) in &arch_entries {
+                for entry in contents {
+                    let name = entry.package.rsplit('/').next().unwrap_or(&entry.package);
+                    for file in &entry.files {
+                        let packages = owners.entry(normalize(file)).or_insert_with(Vec::new);
+                        if !packages.contains(&name) {
+                            packages.push(name);
+                        }
+                    }
+                }
+            }
+
+            // Directories are shared between packages, and are only known as the parents of files.
+            let directories = owners.keys()
+                .flat_map(|path| path.ancestors().skip(1))
+                .collect::<HashSet<&Path>>();
+
+            let covered = |a: &str, b: &str| {
+                relations.get(a).map_or(false, |names| names.contains(b))
+                    || relations.get(b).map_or(false, |names| names.contains(a))
+            };
+
+            let mut conflicts = Vec::new();
+            for (path, packages) in &owners {
+                if packages.len() < 2 || directories.contains(path) || path.as_os_str().as_bytes().ends_with(b"/") {
+                    continue
+                }
+
+                for (i, a) in packages.iter().enumerate() {
+                    for b in &packages[i + 1..] {
+                        if !covered(a, b) {
+                            let (a, b) = if a < b { (a, b) } else { (b, a) };
+                            conflicts.push(FileConflict {
+                                arch: arch.clone(),
+                                path: path.to_path_buf(),
+                                packages: (a.to_string(), b.to_string()),
+                            });
+                        }
+                    }
+                }
+            }
+
+            conflicts
+        })
+        .collect::<Vec<FileConflict>>();
+
+    conflicts.sort();
+    conflicts
+}
+
+fn normalize(path: &Path) -> &Path {
+    path.strip_prefix("./").unwrap_or(path)
+}
+
+/// The names of the packages within a relationship field, without versions or architectures.
+fn relation_names(value: &str) -> Vec<&str> {
+    value.split(|c| c == ',' || c == '|')
+        .filter_map(|relation| {
+            relation.split(|c: char| c.is_whitespace() || c == '(' || c == ':')
+                .find(|name| !name.is_empty())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ContentsEntry, PackageEntry};
+    use std::collections::BTreeMap;
+
+    fn package(name: &str, replaces: Option<&str>) -> PackageEntry {
+        let mut control = BTreeMap::new();
+        control.insert("Package".to_owned(), name.to_owned());
+        if let Some(replaces) = replaces {
+            control.insert("Replaces".to_owned(), replaces.to_owned());
+        }
+
+        PackageEntry {
+            control,
+            filename: PathBuf::from(name),
+            size: 0,
+            md5sum: String::new(),
+            sha1: String::new(),
+            sha256: String::new(),
+            sha512: String::new(),
+        }
+    }
+
+    fn contents(name: &str, files: &[&str]) -> ContentsEntry {
+        ContentsEntry {
+            component: "main".into(),
+            package: ["misc/", name].concat(),
+            files: files.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn conflicting_files() {
+        let mut components = HashMap::new();
+        components.insert("main".to_owned(), vec![
+            package("a", None),
+            package("b", None),
+            package("c", Some("a (<< 2.0), d")),
+        ]);
+
+        let mut entries: Entries = HashMap::new();
+        entries.insert("amd64".into(), (components, vec![
+            contents("a", &["./usr/", "./usr/bin/", "./usr/bin/tool", "./usr/share/doc/a"]),
+            contents("b", &["./usr/", "./usr/bin/", "./usr/bin/tool"]),
+            contents("c", &["./usr/", "./usr/bin/", "./usr/share/doc/a"]),
+        ]));
+
+        assert_eq!(file_conflicts(&entries), vec![FileConflict {
+            arch: "amd64".into(),
+            path: PathBuf::from("usr/bin/tool"),
+            packages: ("a".into(), "b".into()),
+        }]);
+    }
+}
@@ -1,164 +1,524 @@
+mod appstream;
+mod conflicts;
 mod package;
+mod source;
+mod translation;
 
-use config::Config;
+use config::{Config, FileConflicts};
+use deflate::Compression;
+use deflate::write::GzEncoder;
 use iter_reader::IteratorReader;
 use itertools::Itertools;
 use rayon;
 use rayon::prelude::*;
-use std::fs::File;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
 use std::io::{self, Write};
+use std::iter;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
+use tar::{Builder as TarBuilder, Header as TarHeader};
+pub use self::appstream::*;
+pub use self::conflicts::*;
 pub use self::package::*;
+pub use self::source::*;
+pub use self::translation::*;
 use super::*;
 
 pub struct DistFiles<'a> {
     path: &'a Path,
-    entries: Entries
+    entries: Entries,
+    /// Packages of the debian-installer, which are listed separately from other packages.
+    udebs: Entries,
+    sources: SourceEntries,
+    appstream: AppStreamEntries
 }
 
 impl<'a> DistFiles<'a> {
-    pub fn new(path: &'a Path, entries: Entries) -> Self {
-        DistFiles { path, entries }
+    pub fn new(
+        path: &'a Path,
+        entries: Entries,
+        udebs: Entries,
+        sources: SourceEntries,
+        appstream: AppStreamEntries
+    ) -> Self {
+        DistFiles { path, entries, udebs, sources, appstream }
     }
 
-    // pub fn check_for_duplicates(&self) {
-    //     let contents = &self.contents;
-    //     for (arch, (packages, contents)) in &self.entries {
-    //
-    //     }
-    //     contents.windows(2)
-    //         .position(|window| window[0] == window[1])
-    //         .map_or(Ok(()), |pos| {
-    //             let a = &contents[pos];
-    //             let b = &contents[pos+1];
-    //             Err(io::Error::new(
-    //                 io::ErrorKind::Other,
-    //                 format!("{} and {} both have {}", a.1, b.1, a.0.display())
-    //             ))
-    //         })
-    // }
+    /// Reports files which are shipped by more than one package of an architecture.
+    ///
+    /// If conflicts are to be handled as errors, an error is returned when any are found.
+    pub fn check_for_duplicates(&self, handling: FileConflicts) -> io::Result<()> {
+        if handling == FileConflicts::Ignore {
+            return Ok(());
+        }
+
+        info!("checking for files which are shipped by more than one package");
+        let conflicts = file_conflicts(&self.entries);
+        for conflict in &conflicts {
+            match handling {
+                FileConflicts::Error => error!("{}", conflict),
+                _ => warn!("{}", conflict)
+            }
+        }
+
+        if handling == FileConflicts::Error && !conflicts.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("found {} files which are shipped by more than one package", conflicts.len())
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Lists the packages of the `all` architecture within the `Packages` and `Contents` indexes
+    /// of each architecture that their component is published for, if configured to.
+    ///
+    /// This must follow the check for file conflicts, which already compares the packages of the
+    /// `all` architecture against those of every architecture.
+    pub fn merge_arch_all(&mut self, config: &Config) {
+        if config.arch_all.merged() {
+            merge_arch_all(config, &mut self.entries);
+            merge_arch_all(config, &mut self.udebs);
+        }
+    }
 
     pub fn compress_and_release(self, config: &Config, origin: &str, bugs: Option<&str>) -> io::Result<()> {
         let entries = self.entries;
+        let udebs = self.udebs;
+        let sources = self.sources;
+        let appstream = self.appstream;
         let path = self.path;
 
-        // Processes each architecture in parallel, including the contents archives for each arch.
-        entries.into_par_iter().map(|(arch, (packages, contents))| {
-            let arch: &str = &arch;
-            let (contents_res, packages_res) = rayon::join(
-                // Generate and compress the Contents archive for each architecture in parallel.
-                // Contents are processed in a per-architecture manner, rather than per-component.
-                || {
-                    // Sort the files beforehand, so files are easy to track down.
-                    // This will require that we generate the contents archive in advance, sadly.
-                    let mut contents = ContentsIterator::new(contents).collect::<Vec<Vec<u8>>>();
-                    contents.par_sort_unstable_by(|a, b| a.cmp(&b));
+        let translations = if config.translations {
+            collect_translations(&entries)
+        } else {
+            HashMap::new()
+        };
 
-                    let contents_reader = IteratorReader::new(
-                        contents.into_iter(),
-                        Vec::with_capacity(64 * 1024)
+        let components = entries.values()
+            .flat_map(|&(ref components, _)| components.keys().cloned())
+            .collect::<BTreeSet<Component>>();
+
+        // The binary, source, translation, and AppStream indexes are independent of each other,
+        // so write each of them in parallel.
+        let ((binaries_res, sources_res), (translations_res, appstream_res)) = rayon::join(
+            || rayon::join(
+                || {
+                    let (debs_res, udebs_res) = rayon::join(
+                        || binary_indexes(config, path, entries, Binaries::Debs, origin, bugs),
+                        || binary_indexes(config, path, udebs, Binaries::Udebs, origin, bugs)
                     );
 
-                    // Similar to the Packages archives, we also need an uncompressed variant of
-                    // the compressed archives to satisfy APT's detection capabilities.
-                    compress(&["Contents-", &arch].concat(), path, contents_reader, UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS)
+                    debs_res.and(udebs_res)
                 },
-                // Generate & compress each Packages archive for each architecture & component in parallel.
-                // Packages archives are processed in a per-architecture, per-component manner.
-                || {
-                    let arch_dir = match arch {
-                        "amd64" => "binary-amd64",
-                        "arm64" => "binary-arm64",
-                        "armel" => "binary-armel",
-                        "armhf" => "binary-armhf",
-                        "i386" => "binary-i386",
-                        "mips" => "binary-mips",
-                        "mipsel" => "binary-mipsel",
-                        "mips64el" => "binary-mips64el",
-                        "ppc64el" => "binary-ppc64el",
-                        "s390x" => "binary-s390x",
-                        "all" => "binary-all",
-                        arch => panic!("unsupported architecture: {}", arch),
-                    };
-
-                    // Processes the packages of each component in parallel, for this architecture.
-                    packages.into_par_iter().map(|(component, mut packages)| {
-                        // Construct the path where the Packages archives will be written.
-                        let binary_path = &path.join(&component).join(arch_dir);
-
-                        // Sort the packages that were collected before we generate them for writing.
-                        packages.par_sort_unstable_by(|a, b| a.filename.cmp(&b.filename));
-
-                        // Generate the packages content in advance so that we can handle the errors.
-                        let mut generated_packages = Vec::new();
-                        for package in packages {
-                            generated_packages.push(package.generate_entry(origin, bugs)?)
+                || source_indexes(config, path, sources)
+            ),
+            || rayon::join(
+                || translation_indexes(config, path, translations),
+                || if config.appstream {
+                    appstream_indexes(config, path, &components, appstream)
+                } else {
+                    Ok(())
+                }
+            )
+        );
+
+        binaries_res.and(sources_res).and(translations_res).and(appstream_res)
+    }
+}
+
+fn merge_arch_all(config: &Config, entries: &mut Entries) {
+    let (all_packages, all_contents) = match entries.remove("all") {
+        Some(entries) => entries,
+        None => return
+    };
+
+    for (arch, &mut (ref mut components, ref mut contents)) in entries.iter_mut() {
+        for (component, packages) in &all_packages {
+            if config.architectures_of(component).contains(arch) {
+                components.entry(component.clone())
+                    .or_insert_with(Vec::new)
+                    .extend(packages.iter().cloned());
+            }
+        }
+
+        contents.extend(
+            all_contents.iter()
+                .filter(|entry| config.architectures_of(&entry.component).contains(arch))
+                .cloned()
+        );
+    }
+
+    if config.arch_all.separate() {
+        entries.insert("all".into(), (all_packages, all_contents));
+    }
+}
+
+/// The kinds of binary packages, which are written to their own indexes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Binaries {
+    Debs,
+    /// Written to `<component>/debian-installer/binary-<arch>/`, with `Contents-udeb-<arch>`.
+    Udebs,
+}
+
+impl Binaries {
+    fn directory(self, component: &str, arch_dir: &str) -> PathBuf {
+        match self {
+            Binaries::Debs => Path::new(component).join(arch_dir),
+            Binaries::Udebs => Path::new(component).join("debian-installer").join(arch_dir),
+        }
+    }
+
+    fn contents_prefix(self) -> &'static str {
+        match self {
+            Binaries::Debs => "Contents-",
+            Binaries::Udebs => "Contents-udeb-",
+        }
+    }
+}
+
+fn binary_indexes(
+    config: &Config,
+    path: &Path,
+    entries: Entries,
+    binaries: Binaries,
+    origin: &str,
+    bugs: Option<&str>
+) -> io::Result<()> {
+    // Processes each architecture in parallel, including the contents archives for each arch.
+    entries.into_par_iter().map(|(arch, (packages, contents))| {
+        let arch: &str = &arch;
+        let (contents_res, packages_res) = rayon::join(
+            // Generate and compress the Contents archives for each architecture in parallel.
+            || {
+                let name = &[binaries.contents_prefix(), arch].concat();
+                let layout = config.contents_layout;
+                let (suite_res, components_res) = rayon::join(
+                    // Suite-wide contents qualify each package with its component.
+                    || if layout.suite() {
+                        let contents = contents.iter()
+                            .map(|entry| (entry.qualified_package(), entry.files.as_slice()))
+                            .collect();
+
+                        contents_index(config, name, path, contents)
+                    } else {
+                        Ok(())
+                    },
+                    || if layout.component() {
+                        let mut components: HashMap<&str, Vec<(String, &[PathBuf])>> = HashMap::new();
+                        for entry in &contents {
+                            components.entry(entry.component.as_str())
+                                .or_insert_with(Vec::new)
+                                .push((entry.package.clone(), entry.files.as_slice()));
                         }
 
-                        // This iterator will be supplied to our compressor, writing the final
-                        // output with an empty newline between each entry.
-                        let packages_reader = IteratorReader::new(
-                            generated_packages.into_iter().map(|p| p).intersperse(vec![b'\n']),
-                            Vec::with_capacity(64 * 1024)
-                        );
-
-                        // Although we will generate a compressed GZ and XZ archive for our
-                        // repository, APT still requires that we also write an uncompressed variant.
-                        compress("Packages", binary_path, packages_reader, UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS)
-                            .map_err(|why| io::Error::new(
-                                io::ErrorKind::Other,
-                                format!("failed to generate content archive at {}: {}", path.display(), why)
-                            ))?;
-
-                        // A release file also needs to be stored in the same location, after the
-                        // archives have been written. This contains the checksums for each file.
-                        inner_write_release_file(config, binary_path, arch_dir, &component).map_err(|why| io::Error::new(
+                        components.into_par_iter()
+                            .map(|(component, contents)| contents_index(config, name, &path.join(component), contents))
+                            .collect()
+                    } else {
+                        Ok(())
+                    }
+                );
+
+                suite_res.and(components_res)
+            },
+            // Generate & compress each Packages archive for each architecture & component in parallel.
+            // Packages archives are processed in a per-architecture, per-component manner.
+            || {
+                let arch_dir = &["binary-", arch].concat();
+
+                // Processes the packages of each component in parallel, for this architecture.
+                packages.into_par_iter().map(|(component, mut packages)| {
+                    // Construct the path where the Packages archives will be written.
+                    let binary_path = &path.join(binaries.directory(&component, arch_dir));
+                    fs::create_dir_all(binary_path)?;
+
+                    // Sort the packages that were collected before we generate them for writing.
+                    packages.par_sort_unstable_by(|a, b| a.filename.cmp(&b.filename));
+
+                    // Generate the packages content in advance so that we can handle the errors.
+                    let mut generated_packages = Vec::new();
+                    for package in packages {
+                        // The installer does not read translations, so udebs keep their full descriptions.
+                        let translations = config.translations && binaries == Binaries::Debs;
+                        generated_packages.push(package.generate_entry(origin, bugs, translations)?)
+                    }
+
+                    // This iterator will be supplied to our compressor, writing the final
+                    // output with an empty newline between each entry.
+                    let packages_reader = IteratorReader::new(
+                        generated_packages.into_iter().map(|p| p).intersperse(vec![b'\n']),
+                        Vec::with_capacity(64 * 1024)
+                    );
+
+                    // Although we will generate a compressed GZ and XZ archive for our
+                    // repository, APT still requires that we also write an uncompressed variant.
+                    compress("Packages", binary_path, packages_reader, &config.compression.packages)
+                        .map_err(|why| io::Error::new(
                             io::ErrorKind::Other,
-                            format!("failed to create release file for {}: {}", binary_path.display(), why)
-                        ))
-                    }).collect::<io::Result<()>>()
-                }
-            );
+                            format!("failed to generate content archive at {}: {}", path.display(), why)
+                        ))?;
+
+                    // A release file also needs to be stored in the same location, after the
+                    // archives have been written. This contains the checksums for each file.
+                    inner_write_release_file(config, binary_path, arch_dir, &component).map_err(|why| io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("failed to create release file for {}: {}", binary_path.display(), why)
+                    ))
+                }).collect::<io::Result<()>>()
+            }
+        );
+
+        // Check the results to see if we passed.
+        contents_res.map_err(|why| io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to generate content archive at {}: {}", path.display(), why)
+        ))?;
 
-            // Check the results to see if we passed.
-            contents_res.map_err(|why| io::Error::new(
+        packages_res
+    }).collect::<io::Result<()>>()
+}
+
+fn source_indexes(config: &Config, path: &Path, sources: SourceEntries) -> io::Result<()> {
+    // Processes the source packages of each component in parallel.
+    sources.into_par_iter().map(|(component, mut sources)| {
+        let source_path = &path.join(&component).join("source");
+
+        sources.par_sort_unstable_by(|a, b| (&a.directory, &a.dsc).cmp(&(&b.directory, &b.dsc)));
+
+        // The files within each source package are listed before the entries consume them.
+        let contents = if config.contents_layout.component() {
+            let contents = sources.par_iter()
+                .map(|source| source.contents().map(|files| (source.name().to_owned(), files)))
+                .collect::<io::Result<Vec<(String, Vec<PathBuf>)>>>()?;
+            Some(contents)
+        } else {
+            None
+        };
+
+        let mut generated_sources = Vec::new();
+        for source in sources {
+            generated_sources.push(source.generate_entry()?);
+        }
+
+        let sources_reader = IteratorReader::new(
+            Itertools::intersperse(generated_sources.into_iter(), vec![b'\n']),
+            Vec::with_capacity(64 * 1024)
+        );
+
+        compress("Sources", source_path, sources_reader, &config.compression.sources)
+            .map_err(|why| io::Error::new(
                 io::ErrorKind::Other,
-                format!("failed to generate content archive at {}: {}", path.display(), why)
+                format!("failed to generate sources archive at {}: {}", source_path.display(), why)
             ))?;
 
-            packages_res
-        }).collect::<io::Result<()>>()
+        if let Some(contents) = contents {
+            let contents = contents.iter()
+                .map(|&(ref name, ref files)| (name.clone(), files.as_slice()))
+                .collect();
+
+            contents_index(config, "Contents-source", &path.join(&component), contents)?;
+        }
+
+        inner_write_release_file(config, source_path, "source", &component).map_err(|why| io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to create release file for {}: {}", source_path.display(), why)
+        ))
+    }).collect()
+}
+
+/// Formats the value of a control field for a deb822 paragraph, so that every continuation
+/// line is indented, and no line has trailing whitespace.
+fn field_value(value: &str) -> String {
+    let mut lines = value.lines();
+    let mut output = lines.next().map_or_else(String::new, |line| line.trim().to_owned());
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        output.push('\n');
+        if !line.starts_with(' ') && !line.starts_with('\t') {
+            output.push(' ');
+        }
+        output.push_str(line.trim_end());
     }
+
+    output
+}
+
+/// Writes a field of a deb822 paragraph. Values which begin with an empty line, such as the
+/// list of files of a source package, are written without a space after the colon.
+fn write_field(output: &mut Vec<u8>, key: &str, value: &str) {
+    let value = field_value(value);
+    output.extend_from_slice(key.as_bytes());
+    output.push(b':');
+    if !value.starts_with('\n') {
+        output.push(b' ');
+    }
+    output.extend_from_slice(value.as_bytes());
+    output.push(b'\n');
+}
+
+/// Collects the long descriptions of the packages in each component, across every architecture.
+fn collect_translations(entries: &Entries) -> HashMap<Component, Vec<TranslationEntry>> {
+    let mut translations: HashMap<Component, BTreeMap<(String, String), TranslationEntry>> = HashMap::new();
+    for &(ref components, _) in entries.values() {
+        for (component, packages) in components {
+            let component_translations = translations.entry(component.clone()).or_insert_with(BTreeMap::new);
+            for translation in packages.iter().filter_map(PackageEntry::translation) {
+                let key = (translation.package.clone(), translation.description_md5.clone());
+                component_translations.insert(key, translation);
+            }
+        }
+    }
+
+    translations.into_iter()
+        .map(|(component, translations)| (component, translations.into_iter().map(|(_, t)| t).collect()))
+        .collect()
+}
+
+fn translation_indexes(config: &Config, path: &Path, translations: HashMap<Component, Vec<TranslationEntry>>) -> io::Result<()> {
+    // Processes the translations of each component in parallel.
+    translations.into_par_iter().map(|(component, translations)| {
+        let i18n_path = &path.join(&component).join("i18n");
+        fs::create_dir_all(i18n_path)?;
+
+        let translations_reader = IteratorReader::new(
+            Itertools::intersperse(translations.iter().map(TranslationEntry::generate_entry), vec![b'\n']),
+            Vec::with_capacity(64 * 1024)
+        );
+
+        compress("Translation-en", i18n_path, translations_reader, &config.compression.translations)
+            .map_err(|why| io::Error::new(
+                io::ErrorKind::Other,
+                format!("failed to generate translation archive at {}: {}", i18n_path.display(), why)
+            ))
+    }).collect()
+}
+
+/// Writes the `dep11/Components-<arch>.yml` index of each architecture of each component, and
+/// the `dep11/icons-<size>x<size>.tar.gz` archives of the icons that they reference.
+///
+/// The components of packages of the `all` architecture are listed for every architecture.
+fn appstream_indexes(
+    config: &Config,
+    path: &Path,
+    components: &BTreeSet<Component>,
+    mut appstream: AppStreamEntries
+) -> io::Result<()> {
+    let all = appstream.remove("all").unwrap_or_default();
+    let empty = Vec::new();
+
+    components.par_iter().map(|component| {
+        let dep11_path = &path.join(component).join("dep11");
+        fs::create_dir_all(dep11_path)?;
+
+        let all_entries = all.get(component).unwrap_or(&empty);
+        let origin = &[&config.archive, "-", component].concat();
+
+        config.architectures_of(component).par_iter().map(|arch| {
+            let arch_entries = appstream.get(arch).and_then(|map| map.get(component)).unwrap_or(&empty);
+            if arch_entries.is_empty() && all_entries.is_empty() {
+                debug!("no AppStream components for {} in {}", arch, component);
+            }
+
+            let mut app_components = arch_entries.iter()
+                .chain(all_entries)
+                .flat_map(|entry| entry.components.iter())
+                .collect::<Vec<&AppStreamComponent>>();
+            app_components.sort_by(|a, b| (&a.id, &a.package).cmp(&(&b.id, &b.package)));
+            app_components.dedup_by(|a, b| a.id == b.id && a.package == b.package);
+
+            let documents = iter::once(appstream_header(origin))
+                .chain(app_components.into_iter().map(AppStreamComponent::generate_entry));
+
+            let components_reader = IteratorReader::new(documents, Vec::with_capacity(64 * 1024));
+            let name = &["Components-", arch, ".yml"].concat();
+            compress(name, dep11_path, components_reader, &config.compression.appstream)
+                .map_err(|why| io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("failed to generate AppStream archive at {}: {}", dep11_path.display(), why)
+                ))
+        }).collect::<io::Result<()>>()?;
+
+        // Icons are shared by every architecture of the component.
+        let mut icons: BTreeMap<(u32, &str), &[u8]> = BTreeMap::new();
+        let entries = appstream.values()
+            .filter_map(|map| map.get(component))
+            .chain(iter::once(all_entries))
+            .flat_map(|entries| entries.iter());
+
+        for entry in entries {
+            for icon in &entry.icons {
+                icons.insert((icon.size, icon.name.as_str()), icon.data.as_slice());
+            }
+        }
+
+        ICON_SIZES.par_iter().map(|&size| {
+            let name = format!("icons-{0}x{0}.tar.gz", size);
+            let mut archive = TarBuilder::new(GzEncoder::new(File::create(dep11_path.join(&name))?, Compression::Best));
+            for (&(_, name), data) in icons.range((size, "")..(size + 1, "")) {
+                let mut header = TarHeader::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                archive.append_data(&mut header, name, *data)?;
+            }
+
+            archive.into_inner()?.finish().map(|_| ())
+        }).collect()
+    }).collect()
+}
+
+/// Sorts each line of the contents, and writes them to the named Contents index.
+fn contents_index(config: &Config, name: &str, path: &Path, contents: Vec<(String, &[PathBuf])>) -> io::Result<()> {
+    // Sort the files beforehand, so files are easy to track down.
+    // This will require that we generate the contents archive in advance, sadly.
+    let mut contents = ContentsIterator::new(contents).collect::<Vec<Vec<u8>>>();
+    contents.par_sort_unstable_by(|a, b| a.cmp(&b));
+    // Multiple versions of a package may be published, which ship many of the same files.
+    contents.dedup();
+
+    let contents_reader = IteratorReader::new(
+        contents.into_iter(),
+        Vec::with_capacity(64 * 1024)
+    );
+
+    compress(name, path, contents_reader, &config.compression.contents)
+        .map_err(|why| io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to generate content archive at {}: {}", path.display(), why)
+        ))
 }
 
 /// Efficiently generate each line of the Contents file, in style.
-pub struct ContentsIterator {
-    contents: Vec<ContentsEntry>,
+///
+/// Each package is paired with the files that it contains.
+pub struct ContentsIterator<'a> {
+    contents: Vec<(String, &'a [PathBuf])>,
     buffer: Vec<u8>,
     package: usize,
     file: usize
 }
 
-impl ContentsIterator {
-    pub fn new(contents: Vec<ContentsEntry>) -> Self {
+impl<'a> ContentsIterator<'a> {
+    pub fn new(contents: Vec<(String, &'a [PathBuf])>) -> Self {
         ContentsIterator { contents, buffer: Vec::with_capacity(512), package: 0, file: 0 }
     }
 }
 
-impl Iterator for ContentsIterator {
+impl<'a> Iterator for ContentsIterator<'a> {
     type Item = Vec<u8>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let contents_entry = self.contents.get(self.package)?;
-            match contents_entry.files.get(self.file) {
+            let &(ref package, files) = self.contents.get(self.package)?;
+            match files.get(self.file) {
                 Some(path) => {
                     self.file += 1;
                     let path = path.as_os_str().as_bytes();
-                    self.buffer.extend_from_slice(if &path[..2] == b"./" { &path[2..] } else { path });
+                    self.buffer.extend_from_slice(if path.starts_with(b"./") { &path[2..] } else { path });
                     self.buffer.extend_from_slice(b"  ");
-                    self.buffer.extend_from_slice(contents_entry.package.as_bytes());
+                    self.buffer.extend_from_slice(package.as_bytes());
                     self.buffer.push(b'\n');
                     let mut serialized = self.buffer.clone();
                     serialized.shrink_to_fit();
@@ -174,17 +534,34 @@ impl Iterator for ContentsIterator {
     }
 }
 
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ContentsEntry {
+    pub component: String,
+    /// The section and name of the package, as `section/package`.
     pub package: String,
     pub files: Vec<PathBuf>
 }
 
+impl ContentsEntry {
+    /// The package, qualified by its component unless it is `main`, as in suite-wide Contents.
+    pub fn qualified_package(&self) -> String {
+        if self.component == "main" {
+            self.package.clone()
+        } else {
+            [&self.component, "/", &self.package].concat()
+        }
+    }
+}
+
 fn inner_write_release_file(config: &Config, destination: &Path, arch: &str, component: &str) -> io::Result<()> {
     let mut release = File::create(destination.join("Release"))?;
-    writeln!(&mut release, "Archive: {}", config.archive)?;
+    writeln!(&mut release, "Archive: {}", config.suite_name())?;
     writeln!(&mut release, "Version: {}", config.version)?;
     writeln!(&mut release, "Component: {}", component)?;
     writeln!(&mut release, "Origin: {}", config.origin)?;
     writeln!(&mut release, "Label: {}", config.label)?;
+    if config.by_hash.is_some() {
+        writeln!(&mut release, "Acquire-By-Hash: yes")?;
+    }
     writeln!(&mut release, "Architecture: {}", arch)
 }
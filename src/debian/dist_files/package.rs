@@ -1,8 +1,24 @@
+use checksum::hasher;
+use md5::Md5;
 use std::collections::BTreeMap;
 use std::io;
 use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
+use super::{field_value, write_field, TranslationEntry};
 
+/// Fields of the control file which are replaced by fields generated from the pool.
+const GENERATED_FIELDS: &[&str] = &[
+    "Origin",
+    "Filename",
+    "Size",
+    "MD5sum",
+    "SHA1",
+    "SHA256",
+    "SHA512",
+    "Description-md5",
+];
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct PackageEntry {
     pub control: BTreeMap<String, String>,
     pub filename: PathBuf,
@@ -14,8 +30,26 @@ pub struct PackageEntry {
 }
 
 impl PackageEntry {
-    pub fn generate_entry(mut self, origin: &str, bugs: Option<&str>) -> io::Result<Vec<u8>> {
+    /// The full description of the package, for the `Translation-en` index.
+    pub fn translation(&self) -> Option<TranslationEntry> {
+        let package = self.control.get("Package")?;
+        let full_description = field_value(self.control.get("Description")?);
+
+        // The checksum is of the description as it appears in a control file, newline included.
+        let description_md5 = hasher::<Md5, &[u8]>([&full_description, "\n"].concat().as_bytes()).ok()?;
+
+        Some(TranslationEntry { package: package.clone(), description_md5, description: full_description })
+    }
+
+    /// Generates the paragraph of the `Packages` index for this package.
+    ///
+    /// Known fields are written in the order that the Debian archive uses, followed by every
+    /// other field of the control file, so that no metadata is lost. If `translations` is set,
+    /// only the synopsis of the description is written, followed by the `Description-md5` which
+    /// references the long description in the `Translation-en` index.
+    pub fn generate_entry(mut self, origin: &str, bugs: Option<&str>, translations: bool) -> io::Result<Vec<u8>> {
         let mut output = Vec::with_capacity(1024);
+        let translation = if translations { self.translation() } else { None };
         let control = &mut self.control;
 
         fn get_key(map: &mut BTreeMap<String, String>, key: &str) -> io::Result<String> {
@@ -25,63 +59,144 @@ impl PackageEntry {
             ))
         }
 
-        fn write_entry(output: &mut Vec<u8>, key: &[u8], value: &[u8]) {
-            output.extend_from_slice(key);
-            output.extend_from_slice(b": ");
-            output.extend_from_slice(value);
-            output.push(b'\n');
-        }
-
         macro_rules! write_from_map {
             ($key:expr) => {
-                 write_entry(&mut output, $key.as_bytes(), get_key(control, $key)?.as_bytes());
+                 write_field(&mut output, $key, &get_key(control, $key)?);
             }
         }
 
         macro_rules! optional_map {
             ($key:expr) => {
                 if let Some(value) = control.remove($key) {
-                    if let Some(value) = value.lines().next() {
-                        write_entry(&mut output, $key.as_bytes(), value.as_bytes());
-                    }
+                    write_field(&mut output, $key, &value);
                 }
             };
         }
 
+        // Fields which are generated here take precedence over those of the control file.
+        for key in GENERATED_FIELDS {
+            control.remove(*key);
+        }
+
+        if bugs.is_some() {
+            control.remove("Bugs");
+        }
+
         write_from_map!("Package");
+        optional_map!("Source");
         optional_map!("Package-Type");
         write_from_map!("Architecture");
         write_from_map!("Version");
         optional_map!("Multi-Arch");
         optional_map!("Auto-Built-Package");
+        optional_map!("Essential");
+        optional_map!("Build-Essential");
         write_from_map!("Priority");
         write_from_map!("Section");
-        write_entry(&mut output, b"Origin", origin.as_bytes());
+        write_field(&mut output, "Origin", origin);
         write_from_map!("Maintainer");
+        optional_map!("Original-Maintainer");
         write_from_map!("Installed-Size");
         optional_map!("Provides");
         optional_map!("Pre-Depends");
         optional_map!("Depends");
         optional_map!("Recommends");
         optional_map!("Suggests");
+        optional_map!("Enhances");
         optional_map!("Conflicts");
         optional_map!("Breaks");
         optional_map!("Replaces");
-        if let Some(bugs) = bugs {
-            write_entry(&mut output, b"Bugs", bugs.as_bytes());
+        optional_map!("Built-Using");
+        optional_map!("Static-Built-Using");
+        match bugs {
+            Some(bugs) => write_field(&mut output, "Bugs", bugs),
+            None => optional_map!("Bugs")
         }
-        write_entry(&mut output, b"Filename", self.filename.as_os_str().as_bytes());
-        write_entry(&mut output, b"Size", self.size.to_string().as_bytes());
-        write_entry(&mut output, b"MD5sum", self.md5sum.as_bytes());
-        write_entry(&mut output, b"SHA1", self.sha1.as_bytes());
-        write_entry(&mut output, b"SHA256", self.sha256.as_bytes());
-        write_entry(&mut output, b"SHA512", self.sha512.as_bytes());
+        write_field(&mut output, "Filename", &String::from_utf8_lossy(self.filename.as_os_str().as_bytes()));
+        write_field(&mut output, "Size", &self.size.to_string());
+        write_field(&mut output, "MD5sum", &self.md5sum);
+        write_field(&mut output, "SHA1", &self.sha1);
+        write_field(&mut output, "SHA256", &self.sha256);
+        write_field(&mut output, "SHA512", &self.sha512);
         optional_map!("Homepage");
-        optional_map!("Description");
+        match translation {
+            Some(translation) => {
+                if let Some(description) = control.remove("Description") {
+                    write_field(&mut output, "Description", description.lines().next().unwrap_or(""));
+                }
+                write_field(&mut output, "Description-md5", &translation.description_md5);
+            }
+            None => optional_map!("Description")
+        }
+        optional_map!("Tag");
         optional_map!("License");
         optional_map!("Vendor");
         optional_map!("Build-Ids");
 
+        // Every remaining field, including custom `X-` fields, is passed through as it is.
+        for (key, value) in control.iter() {
+            write_field(&mut output, key, value);
+        }
+
         Ok(output)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_line_and_unknown_fields() {
+        let control = [
+            ("Package", "hello"),
+            ("Source", "hello-src"),
+            ("Architecture", "amd64"),
+            ("Version", "1.0"),
+            ("Priority", "optional"),
+            ("Section", "misc"),
+            ("Maintainer", "Someone <someone@example.com>"),
+            ("Installed-Size", "12"),
+            ("Description", "greets the world\n A long description.\n .\n With paragraphs."),
+            ("Built-Using", "gcc (= 8.2.0)"),
+            ("X-Custom", "value"),
+        ];
+
+        let entry = PackageEntry {
+            control: control.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect(),
+            filename: PathBuf::from("pool/bionic/main/binary-amd64/h/hello/hello_1.0_amd64.deb"),
+            size: 1,
+            md5sum: "md5".into(),
+            sha1: "sha1".into(),
+            sha256: "sha256".into(),
+            sha512: "sha512".into(),
+        };
+
+        let output = String::from_utf8(entry.generate_entry("system76", None, false).unwrap()).unwrap();
+        assert_eq!(
+            output,
+            r#"Package: hello
+Source: hello-src
+Architecture: amd64
+Version: 1.0
+Priority: optional
+Section: misc
+Origin: system76
+Maintainer: Someone <someone@example.com>
+Installed-Size: 12
+Built-Using: gcc (= 8.2.0)
+Filename: pool/bionic/main/binary-amd64/h/hello/hello_1.0_amd64.deb
+Size: 1
+MD5sum: md5
+SHA1: sha1
+SHA256: sha256
+SHA512: sha512
+Description: greets the world
+ A long description.
+ .
+ With paragraphs.
+X-Custom: value
+"#
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use bzip2::read::BzDecoder;
+use checksum::Checksums;
+use libflate::gzip;
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use tar;
+use xz2::read::XzDecoder;
+use super::super::Control;
+use super::write_field;
+
+/// Fields which are written immediately after the `Package` field, in this order.
+const ORDERED_FIELDS: &[&str] = &[
+    "Format",
+    "Binary",
+    "Architecture",
+    "Version",
+    "Maintainer",
+    "Uploaders",
+    "Homepage",
+    "Standards-Version",
+    "Vcs-Browser",
+    "Vcs-Git",
+    "Testsuite",
+    "Build-Depends",
+    "Build-Depends-Indep",
+    "Build-Depends-Arch",
+    "Build-Conflicts",
+    "Build-Conflicts-Indep",
+    "Build-Conflicts-Arch",
+    "Package-List",
+];
+
+/// The checksum fields of the `.dsc`, paired with the checksum of the `.dsc` itself.
+const CHECKSUM_FIELDS: &[(&str, fn(&Checksums) -> &str)] = &[
+    ("Files", md5sum),
+    ("Checksums-Sha1", sha1),
+    ("Checksums-Sha256", sha256),
+    ("Checksums-Sha512", sha512),
+];
+
+/// The suffixes of the tarballs which a source package may be unpacked from.
+const TARBALL_EXTENSIONS: &[&str] = &[".tar", ".tar.gz", ".tar.xz", ".tar.bz2"];
+
+fn md5sum(checksums: &Checksums) -> &str { &checksums.md5sum }
+fn sha1(checksums: &Checksums) -> &str { &checksums.sha1 }
+fn sha256(checksums: &Checksums) -> &str { &checksums.sha256 }
+fn sha512(checksums: &Checksums) -> &str { &checksums.sha512 }
+
+/// A source package in the pool, as described by its `.dsc` file.
+pub struct SourceEntry {
+    pub control: Control,
+    /// The directory in the pool where the source package's files are stored.
+    pub directory: PathBuf,
+    /// The file name of the `.dsc` file within the directory.
+    pub dsc: String,
+    pub size: u64,
+    pub checksums: Checksums,
+}
+
+impl SourceEntry {
+    /// The name of the source package.
+    pub fn name(&self) -> &str {
+        self.control.get("Source").map_or(&self.dsc, |name| name.as_str())
+    }
+
+    /// Lists the files of the source package, as they would be after it has been unpacked.
+    pub fn contents(&self) -> io::Result<Vec<PathBuf>> {
+        let tarballs = self.control.get("Files")
+            .into_iter()
+            .flat_map(|files| files.lines())
+            .filter_map(|line| line.split_whitespace().nth(2))
+            // Signatures of the upstream tarballs, such as `.orig.tar.gz.asc`, are not unpacked.
+            .filter(|name| TARBALL_EXTENSIONS.iter().any(|ext| name.ends_with(ext)));
+
+        let mut contents = Vec::new();
+        for tarball in tarballs {
+            contents.extend(tarball_contents(&self.directory.join(tarball))?);
+        }
+
+        contents.sort_unstable();
+        contents.dedup();
+        Ok(contents)
+    }
+
+    pub fn generate_entry(self) -> io::Result<Vec<u8>> {
+        let SourceEntry { mut control, directory, dsc, size, checksums } = self;
+        let mut output = Vec::with_capacity(2048);
+
+        let package = control.remove("Source").ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Source not found in {}", dsc)
+        ))?;
+
+        write_field(&mut output, "Package", &package);
+
+        for &field in ORDERED_FIELDS {
+            if let Some(value) = control.remove(field) {
+                write_field(&mut output, field, &value);
+            }
+        }
+
+        write_field(&mut output, "Directory", &String::from_utf8_lossy(directory.as_os_str().as_bytes()));
+
+        // Each checksum field lists the files of the source package, to which the `.dsc` is added.
+        for &(field, digest) in CHECKSUM_FIELDS {
+            if let Some(files) = control.remove(field) {
+                output.extend_from_slice(field.as_bytes());
+                output.extend_from_slice(b":\n");
+                output.extend_from_slice(format!(" {} {} {}\n", digest(&checksums), size, dsc).as_bytes());
+                for line in files.lines().filter(|line| !line.trim().is_empty()) {
+                    output.extend_from_slice(b" ");
+                    output.extend_from_slice(line.trim().as_bytes());
+                    output.push(b'\n');
+                }
+            }
+        }
+
+        for (key, value) in control.iter() {
+            write_field(&mut output, key, value);
+        }
+
+        Ok(output)
+    }
+}
+
+/// Lists the files within a tarball of a source package, relative to the root of the source.
+fn tarball_contents(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let file = File::open(path)?;
+    let reader: Box<io::Read> = if name.ends_with(".gz") {
+        Box::new(gzip::Decoder::new(file)?)
+    } else if name.ends_with(".xz") {
+        Box::new(XzDecoder::new(file))
+    } else if name.ends_with(".bz2") {
+        Box::new(BzDecoder::new(file))
+    } else if name.ends_with(".tar") {
+        Box::new(file)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported compression format for {}", path.display())
+        ));
+    };
+
+    // The debian tarball is unpacked as it is, and the files of other tarballs are within a root
+    // directory, which is replaced by the name of the component for component tarballs.
+    let (strip_root, prefix) = if name.contains(".debian.tar") {
+        (false, None)
+    } else {
+        let prefix = name.find(".orig-").map(|pos| {
+            let component = &name[pos + 6..];
+            PathBuf::from(&component[..component.find(".tar").unwrap_or_else(|| component.len())])
+        });
+
+        (true, prefix)
+    };
+
+    let mut files = Vec::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue
+        }
+
+        let path = entry.path()?;
+        let components = path.components().filter(|component| *component != Component::CurDir);
+        let relative = components.skip(strip_root as usize).collect::<PathBuf>();
+        if relative.as_os_str().is_empty() {
+            continue
+        }
+
+        files.push(prefix.as_ref().map_or_else(|| relative.clone(), |prefix| prefix.join(&relative)));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    fn tarball(path: &Path, files: &[&str]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for file in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, file, io::empty()).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn contents_skip_signatures() {
+        let dir = tempfile::tempdir().unwrap();
+        tarball(&dir.path().join("foo_1.0.orig.tar"), &["foo-1.0/src/main.c"]);
+        tarball(&dir.path().join("foo_1.0-1.debian.tar"), &["debian/control"]);
+        File::create(dir.path().join("foo_1.0.orig.tar.asc")).unwrap();
+
+        let mut control = Control::new();
+        control.insert("Source".into(), "foo".into());
+        control.insert("Files".into(), [
+            "\n 0 10240 foo_1.0.orig.tar",
+            "\n 0 833 foo_1.0.orig.tar.asc",
+            "\n 0 10240 foo_1.0-1.debian.tar",
+        ].concat());
+
+        let entry = SourceEntry {
+            control,
+            directory: dir.path().to_path_buf(),
+            dsc: "foo_1.0-1.dsc".into(),
+            size: 0,
+            checksums: Checksums::default(),
+        };
+
+        assert_eq!(
+            entry.contents().unwrap(),
+            vec![PathBuf::from("debian/control"), PathBuf::from("src/main.c")]
+        );
+    }
+}
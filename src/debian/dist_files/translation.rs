@@ -0,0 +1,22 @@
+/// The long description of a package, as it is written to the `Translation-en` index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranslationEntry {
+    pub package: String,
+    pub description_md5: String,
+    /// The synopsis, followed by each line of the long description.
+    pub description: String,
+}
+
+impl TranslationEntry {
+    pub fn generate_entry(&self) -> Vec<u8> {
+        let mut output = Vec::with_capacity(self.description.len() + 128);
+        output.extend_from_slice(b"Package: ");
+        output.extend_from_slice(self.package.as_bytes());
+        output.extend_from_slice(b"\nDescription-md5: ");
+        output.extend_from_slice(self.description_md5.as_bytes());
+        output.extend_from_slice(b"\nDescription-en: ");
+        output.extend_from_slice(self.description.as_bytes());
+        output.push(b'\n');
+        output
+    }
+}
@@ -0,0 +1,50 @@
+use bzip2::read::BzDecoder;
+use libflate::gzip;
+use lz4_flex::frame::FrameDecoder;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+/// Extensions of the indexes which may be read, in the order that they are preferred.
+pub const INDEX_EXTENSIONS: &[&str] = &["", ".gz", ".xz", ".bz2", ".zst", ".lz4"];
+
+/// Reads an index within the dist directory, such as `Packages`, from the first of its files
+/// that exists, decompressing it as necessary.
+pub fn read_index(directory: &Path, name: &str) -> io::Result<String> {
+    for extension in INDEX_EXTENSIONS {
+        let path = directory.join([name, extension].concat());
+        if path.exists() {
+            return read_index_file(&path);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no readable copy of {} exists in {}", name, directory.display())
+    ))
+}
+
+/// Reads the index at the given path, which is decompressed according to its extension.
+pub fn read_index_file(path: &Path) -> io::Result<String> {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+    let file = File::open(path)?;
+    let mut reader: Box<Read> = if name.ends_with(".gz") {
+        Box::new(gzip::Decoder::new(file)?)
+    } else if name.ends_with(".xz") {
+        Box::new(XzDecoder::new(file))
+    } else if name.ends_with(".bz2") {
+        Box::new(BzDecoder::new(file))
+    } else if name.ends_with(".zst") {
+        Box::new(ZstdDecoder::new(file)?)
+    } else if name.ends_with(".lz4") {
+        Box::new(FrameDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut index = String::new();
+    reader.read_to_string(&mut index)?;
+    Ok(index)
+}
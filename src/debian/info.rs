@@ -1,4 +1,5 @@
 use std::path::Path;
+use super::DEB_INSTALLER_EXTENSION;
 
 pub fn get_debian_package_info(package: &Path) -> Option<(String, String)> {
     let mut filename = package.file_name()?.to_str()?;
@@ -9,6 +10,8 @@ pub fn get_debian_package_info(package: &Path) -> Option<(String, String)> {
     Some((
         if filename.ends_with("ddeb") {
             [name, "_d"].concat()
+        } else if filename.ends_with(DEB_INSTALLER_EXTENSION) {
+            [name, "_u"].concat()
         } else {
             name.to_owned()
         },
@@ -34,5 +37,11 @@ mod tests {
             get_debian_package_info(&package),
             Some(("name_d".to_owned(), "version".to_owned()))
         );
+
+        let package = Path::new("/some_/pa_th/to/name_version_arch.udeb");
+        assert_eq!(
+            get_debian_package_info(&package),
+            Some(("name_u".to_owned(), "version".to_owned()))
+        );
     }
 }
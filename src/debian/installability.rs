@@ -0,0 +1,430 @@
+use deb_version::compare_versions;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::{deb822, read_index, read_index_file, Control};
+
+/// The number of packages which may be tried while resolving the dependencies of one package,
+/// before the package is assumed to be uninstallable.
+const STEP_LIMIT: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Earlier,
+    EarlierEqual,
+    Equal,
+    LaterEqual,
+    Later,
+}
+
+/// A single alternative of a relationship field, such as `libc6 (>= 2.27)`.
+#[derive(Debug, PartialEq)]
+struct Relation<'a> {
+    name: &'a str,
+    constraint: Option<(Op, &'a str)>,
+    text: &'a str,
+}
+
+impl<'a> Relation<'a> {
+    /// Parses a relation, or returns `None` if it does not apply to the architecture.
+    fn parse(text: &'a str, arch: &str) -> Option<Relation<'a>> {
+        let text = text.trim();
+        let end = text.find(|c: char| c.is_whitespace() || c == '(' || c == '[' || c == '<')
+            .unwrap_or_else(|| text.len());
+        let name = text[..end].split(':').next().unwrap_or("");
+        if name.is_empty() {
+            return None;
+        }
+
+        if let (Some(start), Some(end)) = (text.find('['), text.find(']')) {
+            let arches = text[start + 1..end].split_whitespace().collect::<Vec<&str>>();
+            let negated = arches.iter().any(|a| a.starts_with('!'));
+            let listed = arches.iter().any(|a| a.trim_start_matches('!') == arch);
+            if negated == listed {
+                return None;
+            }
+        }
+
+        let constraint = match (text.find('('), text.find(')')) {
+            (Some(start), Some(end)) if start < end => {
+                let constraint = text[start + 1..end].trim();
+                let split = constraint.find(|c: char| c != '<' && c != '>' && c != '=')
+                    .unwrap_or_else(|| constraint.len());
+                let op = match &constraint[..split] {
+                    "<<" => Op::Earlier,
+                    "<=" | "<" => Op::EarlierEqual,
+                    "=" => Op::Equal,
+                    ">=" | ">" => Op::LaterEqual,
+                    ">>" => Op::Later,
+                    _ => return None,
+                };
+
+                Some((op, constraint[split..].trim()))
+            }
+            _ => None
+        };
+
+        Some(Relation { name, constraint, text })
+    }
+
+    fn allows(&self, version: &str) -> bool {
+        let (op, required) = match self.constraint {
+            Some(constraint) => constraint,
+            None => return true
+        };
+
+        let ordering = compare_versions(version, required);
+        match op {
+            Op::Earlier => ordering == Ordering::Less,
+            Op::EarlierEqual => ordering != Ordering::Greater,
+            Op::Equal => ordering == Ordering::Equal,
+            Op::LaterEqual => ordering != Ordering::Less,
+            Op::Later => ordering == Ordering::Greater,
+        }
+    }
+}
+
+/// Parses each comma-separated clause of a relationship field, which is satisfied by any one of
+/// its `|`-separated alternatives. Clauses which do not apply to the architecture are skipped.
+fn parse_clauses<'a>(field: Option<&'a String>, arch: &str) -> Vec<Vec<Relation<'a>>> {
+    field.map_or_else(Vec::new, |field| {
+        field.split(',')
+            .map(|clause| clause.split('|').filter_map(|alt| Relation::parse(alt, arch)).collect::<Vec<_>>())
+            .filter(|clause| !clause.is_empty())
+            .collect()
+    })
+}
+
+/// A package which may be installed, and its relationships with other packages.
+struct Candidate<'a> {
+    name: &'a str,
+    version: &'a str,
+    provides: Vec<Relation<'a>>,
+    /// Clauses of `Pre-Depends` and `Depends`, each of which must be satisfied.
+    depends: Vec<Vec<Relation<'a>>>,
+    /// Alternatives of `Conflicts` and `Breaks`, none of which may be installed beside it.
+    conflicts: Vec<Relation<'a>>,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(control: &'a Control, arch: &str) -> Option<Self> {
+        let field = |name: &str| control.get(name).map(String::as_str).unwrap_or("");
+        let clauses = |name: &str| parse_clauses(control.get(name), arch);
+
+        let name = field("Package");
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut depends = clauses("Pre-Depends");
+        depends.extend(clauses("Depends"));
+
+        Some(Candidate {
+            name,
+            version: field("Version"),
+            provides: clauses("Provides").into_iter().flatten().collect(),
+            depends,
+            conflicts: clauses("Conflicts").into_iter().chain(clauses("Breaks")).flatten().collect(),
+        })
+    }
+
+    /// Whether the package, or a package that it provides, satisfies the relation.
+    fn satisfies(&self, relation: &Relation) -> bool {
+        if self.name == relation.name && relation.allows(self.version) {
+            return true;
+        }
+
+        self.provides.iter().any(|provided| {
+            provided.name == relation.name && match (relation.constraint, provided.constraint) {
+                (None, _) => true,
+                (Some(_), Some((Op::Equal, version))) => relation.allows(version),
+                (Some(_), _) => false,
+            }
+        })
+    }
+}
+
+/// A package which cannot be installed, and the reason why.
+#[derive(Debug, PartialEq)]
+pub struct Uninstallable {
+    pub package: String,
+    pub version: String,
+    /// The architecture that the package was checked on.
+    pub architecture: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Uninstallable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} cannot be installed on {}: {}", self.package, self.version, self.architecture, self.reason)
+    }
+}
+
+/// Every package which is available to be installed on an architecture.
+struct Universe<'a> {
+    arch: &'a str,
+    candidates: Vec<Candidate<'a>>,
+    /// The number of candidates, from the start, which belong to the suite being checked.
+    local: usize,
+    /// Candidates by their names, and the names of the packages that they provide.
+    names: HashMap<&'a str, Vec<usize>>,
+}
+
+impl<'a> Universe<'a> {
+    fn new(local: &'a [Control], upstream: &'a [Control], arch: &'a str) -> Self {
+        let on_arch = |control: &&'a Control| {
+            control.get("Architecture").map_or(false, |a| a == arch || a == "all")
+        };
+
+        let candidates = local.iter().filter(on_arch)
+            .filter_map(|control| Candidate::new(control, arch))
+            .collect::<Vec<_>>();
+        let local = candidates.len();
+
+        let mut universe = Universe { arch, candidates, local, names: HashMap::new() };
+        universe.candidates.extend(
+            upstream.iter().filter(on_arch).filter_map(|control| Candidate::new(control, arch))
+        );
+
+        for (id, candidate) in universe.candidates.iter().enumerate() {
+            universe.names.entry(candidate.name).or_insert_with(Vec::new).push(id);
+            for provided in &candidate.provides {
+                universe.names.entry(provided.name).or_insert_with(Vec::new).push(id);
+            }
+        }
+
+        // The newest versions are preferred, and packages are preferred over those providing them.
+        for (&name, ids) in &mut universe.names {
+            let candidates = &universe.candidates;
+            ids.dedup();
+            ids.sort_by(|&a, &b| {
+                let (a, b) = (&candidates[a], &candidates[b]);
+                (b.name == name).cmp(&(a.name == name))
+                    .then_with(|| compare_versions(b.version, a.version))
+            });
+        }
+
+        universe
+    }
+
+    /// The candidates which satisfy the relation.
+    fn providers<'s>(&'s self, relation: &'s Relation) -> impl Iterator<Item = usize> + 's {
+        self.names.get(relation.name).into_iter()
+            .flat_map(|ids| ids.iter().cloned())
+            .filter(move |&id| self.candidates[id].satisfies(relation))
+    }
+
+    /// Whether the two candidates may not be installed together.
+    fn conflicts(&self, a: usize, b: usize) -> bool {
+        let (x, y) = (&self.candidates[a], &self.candidates[b]);
+        a != b && (x.name == y.name
+            || x.conflicts.iter().any(|relation| y.satisfies(relation))
+            || y.conflicts.iter().any(|relation| x.satisfies(relation)))
+    }
+
+    /// Checks each package of the suite, in parallel.
+    fn uninstallable(&self) -> Vec<Uninstallable> {
+        (0..self.local).into_par_iter()
+            .filter_map(|id| {
+                let mut solver = Solver { universe: self, steps: 0, installed: vec![false; self.candidates.len()] };
+                solver.installed[id] = true;
+                solver.solve(&mut vec![id], 0).err().map(|reason| {
+                    let candidate = &self.candidates[id];
+                    Uninstallable {
+                        package: candidate.name.to_owned(),
+                        version: candidate.version.to_owned(),
+                        architecture: self.arch.to_owned(),
+                        reason,
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+/// Searches for a set of packages which satisfies every dependency of the first, without
+/// any conflicts between them, backtracking through the alternatives of each dependency.
+struct Solver<'u, 'a: 'u> {
+    universe: &'u Universe<'a>,
+    steps: usize,
+    installed: Vec<bool>,
+}
+
+impl<'u, 'a: 'u> Solver<'u, 'a> {
+    /// The dependencies of the packages before `from` are already satisfied, as packages are
+    /// only ever added to the set while it is searched.
+    fn solve(&mut self, set: &mut Vec<usize>, mut from: usize) -> Result<(), String> {
+        let universe = self.universe;
+
+        let (package, clause) = loop {
+            let package = match set.get(from) {
+                Some(&package) => package,
+                None => return Ok(())
+            };
+
+            let unsatisfied = universe.candidates[package].depends.iter().find(|clause| {
+                !clause.iter().any(|relation| universe.providers(relation).any(|id| self.installed[id]))
+            });
+
+            match unsatisfied {
+                Some(clause) => break (&universe.candidates[package], clause),
+                None => from += 1
+            }
+        };
+
+        let mut options = Vec::new();
+        for relation in clause {
+            for id in universe.providers(relation) {
+                if !options.contains(&id) {
+                    options.push(id);
+                }
+            }
+        }
+
+        let describe = || clause.iter().map(|relation| relation.text).collect::<Vec<_>>().join(" | ");
+        if options.is_empty() {
+            return Err(format!("{} {} depends on {}, which is not available", package.name, package.version, describe()));
+        }
+
+        let mut reason = None;
+        for option in options {
+            if let Some(&installed) = set.iter().find(|&&installed| universe.conflicts(installed, option)) {
+                if reason.is_none() {
+                    let (option, installed) = (&universe.candidates[option], &universe.candidates[installed]);
+                    reason = Some(format!(
+                        "{} {} depends on {}, but {} {} conflicts with {} {}",
+                        package.name, package.version, describe(),
+                        option.name, option.version, installed.name, installed.version
+                    ));
+                }
+
+                continue
+            }
+
+            self.steps += 1;
+            if self.steps > STEP_LIMIT {
+                return Err(format!("gave up after trying {} packages", STEP_LIMIT));
+            }
+
+            set.push(option);
+            self.installed[option] = true;
+            match self.solve(set, from) {
+                Ok(()) => return Ok(()),
+                Err(why) => if reason.is_none() { reason = Some(why); }
+            }
+
+            self.installed[option] = false;
+            set.pop();
+        }
+
+        Err(reason.unwrap())
+    }
+}
+
+/// Checks that every package of the dists may be installed on each architecture, alongside the
+/// packages of the upstream `Packages` indexes.
+pub fn uninstallable_packages(
+    dists: &Path,
+    architectures: &[String],
+    upstream: &[PathBuf]
+) -> io::Result<Vec<Uninstallable>> {
+    info!("checking the installability of the packages in {}", dists.display());
+
+    let mut upstream_packages = Vec::new();
+    for path in upstream {
+        let index = read_index_file(path).map_err(|why| io::Error::new(
+            why.kind(),
+            format!("failed to read upstream packages from {}: {}", path.display(), why)
+        ))?;
+        upstream_packages.extend(deb822::parse(&index));
+    }
+
+    let mut components = Vec::new();
+    for entry in dists.read_dir()? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            components.push(entry.path());
+        }
+    }
+
+    let mut uninstallable = Vec::new();
+    for arch in architectures {
+        let mut seen = HashSet::new();
+        let mut packages = Vec::new();
+        for component in &components {
+            for binary in &[arch.as_str(), "all"] {
+                let index = match read_index(&component.join(["binary-", binary].concat()), "Packages") {
+                    Ok(index) => index,
+                    Err(ref why) if why.kind() == io::ErrorKind::NotFound => continue,
+                    Err(why) => return Err(why),
+                };
+
+                for control in deb822::parse(&index) {
+                    let key = ["Package", "Version", "Architecture"].iter()
+                        .map(|field| control.get(*field).cloned().unwrap_or_default())
+                        .collect::<Vec<String>>();
+                    if seen.insert(key) {
+                        packages.push(control);
+                    }
+                }
+            }
+        }
+
+        uninstallable.extend(Universe::new(&packages, &upstream_packages, arch).uninstallable());
+    }
+
+    Ok(uninstallable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(fields: &[(&str, &str)]) -> Control {
+        fields.iter().map(|&(key, value)| (key.to_owned(), value.to_owned())).collect()
+    }
+
+    #[test]
+    fn relations() {
+        assert_eq!(Relation::parse(" libc6:any (>= 2.27) ", "amd64"), Some(Relation {
+            name: "libc6",
+            constraint: Some((Op::LaterEqual, "2.27")),
+            text: "libc6:any (>= 2.27)",
+        }));
+
+        assert!(Relation::parse("foo [i386]", "amd64").is_none());
+        assert!(Relation::parse("foo [!amd64]", "amd64").is_none());
+        assert!(Relation::parse("foo [!i386]", "amd64").is_some());
+    }
+
+    #[test]
+    fn installability() {
+        let local = vec![
+            control(&[("Package", "app"), ("Version", "1.0"), ("Architecture", "amd64"),
+                ("Depends", "libfoo (>= 2) | libbar, base")]),
+            control(&[("Package", "libbar"), ("Version", "1.0"), ("Architecture", "all"),
+                ("Conflicts", "base")]),
+            control(&[("Package", "libfoo"), ("Version", "2.1"), ("Architecture", "amd64")]),
+            control(&[("Package", "broken"), ("Version", "0.1"), ("Architecture", "amd64"),
+                ("Depends", "missing (>> 1)")]),
+            control(&[("Package", "clash"), ("Version", "0.1"), ("Architecture", "amd64"),
+                ("Depends", "libbar, base-virtual")]),
+        ];
+
+        let upstream = vec![
+            control(&[("Package", "base"), ("Version", "3"), ("Architecture", "amd64"),
+                ("Provides", "base-virtual")]),
+        ];
+
+        let universe = Universe::new(&local, &upstream, "amd64");
+        let uninstallable = universe.uninstallable();
+        assert_eq!(uninstallable.len(), 2);
+        assert_eq!(uninstallable[0].package, "broken");
+        assert_eq!(uninstallable[0].reason, "broken 0.1 depends on missing (>> 1), which is not available");
+        assert_eq!(uninstallable[1].package, "clash");
+        assert_eq!(uninstallable[1].reason, "clash 0.1 depends on base-virtual, but base 3 conflicts with libbar 1.0");
+    }
+}
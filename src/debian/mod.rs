@@ -1,11 +1,20 @@
 pub mod archive;
+pub mod deb822;
 pub mod dist_files;
 pub mod missing;
+pub mod index;
 pub mod info;
+pub mod installability;
+pub mod pdiff;
+pub mod release;
 
 pub use self::dist_files::*;
 pub use self::missing::*;
+pub use self::index::*;
 pub use self::info::*;
+pub use self::installability::*;
+pub use self::pdiff::*;
+pub use self::release::*;
 
 use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
@@ -14,6 +23,7 @@ use compress::*;
 pub const DEB_SOURCE_EXTENSIONS: &[&str] = &[".tar.gz", ".tar.xz", ".dsc"];
 pub const DEB_DEBUG_EXTENSION: &str = ".ddeb";
 pub const DEB_EXTENSION: &str = ".deb";
+pub const DEB_INSTALLER_EXTENSION: &str = ".udeb";
 
 pub type Arch = String;
 pub type Component = String;
@@ -21,6 +31,8 @@ pub type Package = String;
 
 pub type Control = BTreeMap<String, String>;
 pub type Entries = HashMap<Arch, (HashMap<Component, Vec<PackageEntry>>, Vec<ContentsEntry>)>;
+pub type SourceEntries = HashMap<Component, Vec<SourceEntry>>;
+pub type AppStreamEntries = HashMap<Arch, HashMap<Component, Vec<AppStreamEntry>>>;
 
 pub type ContentList = Vec<(PathBuf, String)>;
 
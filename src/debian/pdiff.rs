@@ -0,0 +1,165 @@
+use std::io::{self, Write};
+use super::deb822;
+
+/// The directory name suffix of the patches for an index, such as `Packages.diff`.
+pub const PDIFF_SUFFIX: &str = ".diff";
+
+/// The SHA256 checksum and size of a file, as recorded in a `.diff/Index` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PDiffFile {
+    pub sha256: String,
+    pub size: u64,
+    pub name: String,
+}
+
+/// A patch which transforms a previous revision of an index into the revision that followed it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PDiffPatch {
+    /// The revision of the index which this patch is applied to.
+    pub history: PDiffFile,
+    /// The uncompressed ed-style patch.
+    pub patch: PDiffFile,
+    /// The gzip-compressed patch, which is what APT will download.
+    pub download: PDiffFile,
+}
+
+/// An in-memory representation of the `Index` file within a `Packages.diff` or `Sources.diff`
+/// directory. Patches are ordered from the oldest to the newest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PDiffIndex {
+    pub current_sha256: String,
+    pub current_size: u64,
+    pub patches: Vec<PDiffPatch>,
+}
+
+impl PDiffIndex {
+    /// Parses an existing `Index` file, so that its history may be extended.
+    pub fn parse(input: &str) -> io::Result<PDiffIndex> {
+        let fields = deb822::parse(input).into_iter().next().unwrap_or_default();
+        let invalid = |why: &str| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid pdiff index: {}", why)
+        );
+
+        let (current_sha256, current_size) = {
+            let current = fields.get("SHA256-Current").ok_or_else(|| invalid("missing SHA256-Current"))?;
+            let mut current = current.split_whitespace();
+            match (current.next(), current.next().and_then(|size| size.parse::<u64>().ok())) {
+                (Some(sha256), Some(size)) => (sha256.to_owned(), size),
+                _ => return Err(invalid("malformed SHA256-Current"))
+            }
+        };
+
+        let files = |field: &str| -> io::Result<Vec<PDiffFile>> {
+            fields.get(field).map_or(Ok(Vec::new()), |value| {
+                value.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| {
+                        let mut values = line.split_whitespace();
+                        match (values.next(), values.next().and_then(|size| size.parse::<u64>().ok()), values.next()) {
+                            (Some(sha256), Some(size), Some(name)) => Ok(PDiffFile {
+                                sha256: sha256.to_owned(),
+                                size,
+                                name: name.to_owned()
+                            }),
+                            _ => Err(invalid(&format!("malformed {} entry: {}", field, line)))
+                        }
+                    })
+                    .collect()
+            })
+        };
+
+        let history = files("SHA256-History")?;
+        let patches = files("SHA256-Patches")?;
+        let download = files("SHA256-Download")?;
+
+        if history.len() != patches.len() || history.len() != download.len() {
+            return Err(invalid("the number of history, patch, and download entries differ"));
+        }
+
+        let patches = history.into_iter()
+            .zip(patches.into_iter())
+            .zip(download.into_iter())
+            .map(|((history, patch), download)| PDiffPatch { history, patch, download })
+            .collect();
+
+        Ok(PDiffIndex { current_sha256, current_size, patches })
+    }
+
+    /// Discards the oldest patches which exceed the limit, returning the patches that were discarded.
+    pub fn expire(&mut self, limit: usize) -> Vec<PDiffPatch> {
+        let excess = self.patches.len().saturating_sub(limit);
+        self.patches.drain(..excess).collect()
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "SHA256-Current: {} {}", self.current_sha256, self.current_size)?;
+
+        let fields: [(&str, fn(&PDiffPatch) -> &PDiffFile); 3] = [
+            ("SHA256-History", |p| &p.history),
+            ("SHA256-Patches", |p| &p.patch),
+            ("SHA256-Download", |p| &p.download),
+        ];
+
+        for &(field, file) in &fields {
+            writeln!(out, "{}:", field)?;
+            for patch in &self.patches {
+                let file = file(patch);
+                writeln!(out, " {} {:>7} {}", file.sha256, file.size, file.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(sha256: &str, size: u64, name: &str) -> PDiffFile {
+        PDiffFile { sha256: sha256.into(), size, name: name.into() }
+    }
+
+    #[test]
+    fn pdiff_index() {
+        let mut index = PDiffIndex {
+            current_sha256: "current".into(),
+            current_size: 4096,
+            patches: vec![PDiffPatch {
+                history: file("old", 2048, "2018-10-01-1200.00"),
+                patch: file("patch", 128, "2018-10-01-1200.00"),
+                download: file("download", 64, "2018-10-01-1200.00.gz"),
+            }]
+        };
+
+        index.patches.push(PDiffPatch {
+            history: file("older", 1024, "2018-10-02-1200.00"),
+            patch: file("patch2", 256, "2018-10-02-1200.00"),
+            download: file("download2", 96, "2018-10-02-1200.00.gz"),
+        });
+
+        let discarded = index.expire(1);
+
+        assert_eq!(discarded.len(), 1);
+        assert_eq!(discarded[0].history.name, "2018-10-01-1200.00");
+
+        let mut output = Vec::new();
+        index.write(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(
+            output,
+            r#"SHA256-Current: current 4096
+SHA256-History:
+ older    1024 2018-10-02-1200.00
+SHA256-Patches:
+ patch2     256 2018-10-02-1200.00
+SHA256-Download:
+ download2      96 2018-10-02-1200.00.gz
+"#
+        );
+
+        assert_eq!(PDiffIndex::parse(&output).unwrap(), index);
+    }
+}
@@ -0,0 +1,296 @@
+use checksum::{checksums, hasher, Checksums};
+use misc;
+use rayon::prelude::*;
+use sha2::Sha256;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use super::deb822;
+use walkdir::WalkDir;
+
+/// Directories which contain copies of indexes, addressed by their checksum.
+pub const BY_HASH: &str = "by-hash";
+
+/// Files at the root of the dist directory which are derived from the `Release` file.
+const RELEASE_FILES: &[&str] = &["Release", "InRelease", "Release.gpg"];
+
+/// A file within the dist directory, and the checksums that APT will validate it against.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReleaseEntry {
+    /// The path of the file, relative to the dist directory.
+    pub path: String,
+    pub size: u64,
+    pub checksums: Checksums,
+}
+
+/// An in-memory representation of the top-level `Release` file of a suite.
+#[derive(Clone, Debug)]
+pub struct Release {
+    pub origin: String,
+    pub label: String,
+    pub suite: String,
+    pub version: String,
+    pub codename: String,
+    /// The date of the release, formatted according to RFC 2822.
+    pub date: String,
+    /// The date that clients should stop trusting the release, formatted according to RFC 2822.
+    pub valid_until: Option<String>,
+    /// Whether APT should only install packages from this suite when explicitly asked to.
+    pub not_automatic: bool,
+    /// Whether upgrades to installed packages are still installed automatically.
+    pub but_automatic_upgrades: bool,
+    /// Whether indexes may also be fetched from the `by-hash` directory beside them.
+    pub acquire_by_hash: bool,
+    /// Whether packages of the `all` architecture are also listed in the `Packages` index of
+    /// each architecture, so that clients need not fetch `binary-all/Packages`.
+    pub no_support_for_architecture_all: bool,
+    pub architectures: Vec<String>,
+    pub components: Vec<String>,
+    pub description: String,
+    pub entries: Vec<ReleaseEntry>,
+}
+
+impl Release {
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "Origin: {}", self.origin)?;
+        writeln!(out, "Label: {}", self.label)?;
+        writeln!(out, "Suite: {}", self.suite)?;
+        writeln!(out, "Version: {}", self.version)?;
+        writeln!(out, "Codename: {}", self.codename)?;
+        writeln!(out, "Date: {}", self.date)?;
+        if let Some(ref valid_until) = self.valid_until {
+            writeln!(out, "Valid-Until: {}", valid_until)?;
+        }
+        if self.not_automatic {
+            writeln!(out, "NotAutomatic: yes")?;
+        }
+        if self.but_automatic_upgrades {
+            writeln!(out, "ButAutomaticUpgrades: yes")?;
+        }
+        if self.acquire_by_hash {
+            writeln!(out, "Acquire-By-Hash: yes")?;
+        }
+        if self.no_support_for_architecture_all {
+            writeln!(out, "No-Support-for-Architecture-all: Packages")?;
+        }
+        writeln!(out, "Architectures: {}", self.architectures.join(" "))?;
+        writeln!(out, "Components: {}", self.components.join(" "))?;
+        writeln!(out, "Description: {}", self.description)?;
+
+        let fields: [(&str, fn(&Checksums) -> &str); 4] = [
+            ("MD5Sum", |c| c.md5sum.as_str()),
+            ("SHA1", |c| c.sha1.as_str()),
+            ("SHA256", |c| c.sha256.as_str()),
+            ("SHA512", |c| c.sha512.as_str()),
+        ];
+
+        for &(field, digest) in &fields {
+            writeln!(out, "{}:", field)?;
+            for entry in &self.entries {
+                writeln!(out, " {} {:>16} {}", digest(&entry.checksums), entry.size, entry.path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces the `Date` of an existing `Release` file, along with its `Valid-Until` field, which
+/// is removed when no date is given. Every other line is kept as it was.
+pub fn refresh_dates(release: &str, date: &str, valid_until: Option<&str>) -> String {
+    let mut out = String::with_capacity(release.len());
+    for line in release.lines() {
+        if line.starts_with("Valid-Until:") {
+            continue
+        }
+
+        if line.starts_with("Date:") {
+            out.push_str(&["Date: ", date, "\n"].concat());
+            if let Some(valid_until) = valid_until {
+                out.push_str(&["Valid-Until: ", valid_until, "\n"].concat());
+            }
+            continue
+        }
+
+        out.push_str(line);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Collects the size and checksums of every index within the dist directory, in parallel.
+pub fn collect_entries(base: &Path) -> io::Result<Vec<ReleaseEntry>> {
+    let mut paths = Vec::new();
+    let walker = WalkDir::new(base)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|e| e.file_name() != OsStr::new(BY_HASH));
+
+    for entry in walker {
+        let entry = entry.map_err(|why| io::Error::new(
+            io::ErrorKind::Other,
+            format!("failed to walk {}: {}", base.display(), why)
+        ))?;
+
+        if entry.file_type().is_dir() {
+            continue
+        }
+
+        let relative = entry.path().strip_prefix(base).unwrap();
+        if entry.depth() == 1 && RELEASE_FILES.iter().any(|&name| relative == Path::new(name)) {
+            continue
+        }
+
+        let relative = relative.to_str().ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a valid UTF-8 path", entry.path().display())
+        ))?;
+
+        paths.push((relative.to_owned(), entry.path().to_path_buf()));
+    }
+
+    paths.into_par_iter()
+        .map(|(relative, path)| {
+            let size = path.metadata()?.len();
+            Ok(ReleaseEntry { path: relative, size, checksums: checksums(&path)? })
+        })
+        .collect()
+}
+
+/// Validates that every file listed in the `Release` file of the dist directory exists, and that
+/// its size and SHA256 checksum are what the `Release` file claims.
+pub fn validate(base: &Path) -> io::Result<()> {
+    info!("validating the release file of {}", base.display());
+
+    let release = misc::read_to_string(base.join("Release"))?;
+    let fields = deb822::parse(&release).into_iter().next().unwrap_or_default();
+    let entries = fields.get("SHA256").ok_or_else(|| io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Release file does not contain a SHA256 field"
+    ))?;
+
+    entries.lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<&str>>()
+        .into_par_iter()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let (digest, size, path) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(digest), Some(size), Some(path)) => (digest, size, path),
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid SHA256 entry in Release file: {}", line)
+                ))
+            };
+
+            let file = base.join(path);
+            let metadata = file.metadata().map_err(|why| io::Error::new(
+                why.kind(),
+                format!("{} is listed in the Release file, but cannot be read: {}", path, why)
+            ))?;
+
+            if metadata.len().to_string() != size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is {} bytes, but the Release file expects {}", path, metadata.len(), size)
+                ));
+            }
+
+            if File::open(&file).and_then(hasher::<Sha256, File>)? != digest {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("SHA256 checksum of {} does not match the Release file", path)
+                ));
+            }
+
+            Ok(())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refreshed_dates() {
+        let release = "Suite: bionic\nDate: Mon, 01 Oct 2018 12:00:00 UTC\n\
+            Valid-Until: Mon, 08 Oct 2018 12:00:00 UTC\nSHA256:\n abc 1 main/Release\n";
+
+        assert_eq!(
+            refresh_dates(release, "Tue, 02 Oct 2018 12:00:00 UTC", Some("Tue, 09 Oct 2018 12:00:00 UTC")),
+            "Suite: bionic\nDate: Tue, 02 Oct 2018 12:00:00 UTC\n\
+                Valid-Until: Tue, 09 Oct 2018 12:00:00 UTC\nSHA256:\n abc 1 main/Release\n"
+        );
+
+        assert_eq!(
+            refresh_dates(release, "Tue, 02 Oct 2018 12:00:00 UTC", None),
+            "Suite: bionic\nDate: Tue, 02 Oct 2018 12:00:00 UTC\nSHA256:\n abc 1 main/Release\n"
+        );
+    }
+
+    #[test]
+    fn release_file() {
+        let release = Release {
+            origin: "system76".into(),
+            label: "System76".into(),
+            suite: "proposed".into(),
+            version: "18.04".into(),
+            codename: "bionic".into(),
+            date: "Mon, 01 Oct 2018 12:00:00 UTC".into(),
+            valid_until: Some("Mon, 08 Oct 2018 12:00:00 UTC".into()),
+            not_automatic: true,
+            but_automatic_upgrades: true,
+            acquire_by_hash: true,
+            no_support_for_architecture_all: true,
+            architectures: vec!["i386".into(), "amd64".into(), "all".into()],
+            components: vec!["main".into(), "proposed".into()],
+            description: "System76 (bionic 18.04)".into(),
+            entries: vec![
+                ReleaseEntry {
+                    path: "main/binary-amd64/Packages".into(),
+                    size: 1024,
+                    checksums: Checksums {
+                        md5sum: "md5".into(),
+                        sha1: "sha1".into(),
+                        sha256: "sha256".into(),
+                        sha512: "sha512".into(),
+                    }
+                }
+            ]
+        };
+
+        let mut output = Vec::new();
+        release.write(&mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"Origin: system76
+Label: System76
+Suite: proposed
+Version: 18.04
+Codename: bionic
+Date: Mon, 01 Oct 2018 12:00:00 UTC
+Valid-Until: Mon, 08 Oct 2018 12:00:00 UTC
+NotAutomatic: yes
+ButAutomaticUpgrades: yes
+Acquire-By-Hash: yes
+No-Support-for-Architecture-all: Packages
+Architectures: i386 amd64 all
+Components: main proposed
+Description: System76 (bionic 18.04)
+MD5Sum:
+ md5             1024 main/binary-amd64/Packages
+SHA1:
+ sha1             1024 main/binary-amd64/Packages
+SHA256:
+ sha256             1024 main/binary-amd64/Packages
+SHA512:
+ sha512             1024 main/binary-amd64/Packages
+"#
+        );
+    }
+}
@@ -1,7 +1,10 @@
 extern crate apt_repo_crawler;
+extern crate bincode;
 extern crate bus_writer;
+extern crate bzip2;
 #[macro_use]
 extern crate cascade;
+extern crate chrono;
 extern crate crossbeam_channel;
 extern crate deb_version;
 extern crate debarchive;
@@ -14,21 +17,27 @@ extern crate hex_view;
 extern crate itertools;
 extern crate libc;
 extern crate libflate;
+extern crate lz4_flex;
 extern crate md5;
 extern crate parallel_getter;
+extern crate pgp;
+extern crate quick_xml;
 extern crate rayon;
 extern crate regex;
 extern crate reqwest;
 extern crate select;
 extern crate serde;
+extern crate serde_json;
 extern crate sha1;
 extern crate sha2;
 extern crate subprocess;
+extern crate tar;
 extern crate tempfile;
 extern crate toml;
 extern crate utime;
 extern crate walkdir;
 extern crate xz2;
+extern crate zstd;
 
 #[macro_use]
 extern crate clap;
@@ -48,6 +57,7 @@ pub mod debian;
 pub mod iter_reader;
 pub mod misc;
 mod repo;
+pub mod signer;
 pub mod url;
 
 use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
@@ -120,7 +130,12 @@ fn main() {
                 .about("only builds the pool"))
             .subcommand(SubCommand::with_name("dist")
                 .alias("d")
-                .about("only builds the dist files"))
+                .about("only builds the dist files")
+                .arg(Arg::with_name("clear-cache")
+                    .long("clear-cache")
+                    .help("processes every archive in the pool again, instead of using the dist cache")))
+        ).subcommand(SubCommand::with_name("check")
+            .about("Reports each published package whose dependencies cannot be satisfied")
         ).subcommand(SubCommand::with_name("clean")
             .about("cleans excess packages from the repository")
         ).subcommand(SubCommand::with_name("config")
@@ -128,13 +143,57 @@ fn main() {
             .alias("c")
             .arg(Arg::with_name("key").required(false))
             .arg(Arg::with_name("value").required(false))
+        ).subcommand(SubCommand::with_name("list")
+            .about("Lists the packages which are published in the repository")
+            .arg(Arg::with_name("pattern")
+                .help("only lists packages whose names match this glob pattern"))
+            .arg(Arg::with_name("component")
+                .long("component")
+                .takes_value(true)
+                .help("only lists packages within this component"))
+            .arg(Arg::with_name("arch")
+                .long("arch")
+                .takes_value(true)
+                .help("only lists packages of this architecture"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("prints the packages as JSON"))
+        ).subcommand(SubCommand::with_name("show")
+            .about("Shows every published version of a package, with its control fields")
+            .arg(Arg::with_name("package").required(true))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("prints the packages as JSON"))
+        ).subcommand(SubCommand::with_name("rdepends")
+            .about("Lists the published packages which depend upon a package")
+            .arg(Arg::with_name("package").required(true))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("prints the dependents as JSON"))
         ).subcommand(SubCommand::with_name("remove")
             .about("removes the specified packages from the repository")
             .alias("r")
             .arg(Arg::with_name("packages").multiple(true).required(true))
+        ).subcommand(SubCommand::with_name("refresh")
+            .about("Updates the dates of the published Release files and signs them again")
+        ).subcommand(SubCommand::with_name("rollback")
+            .about("Restores the dists that were published before the most recent generation")
+        ).subcommand(SubCommand::with_name("snapshot")
+            .about("Records, lists, and publishes snapshots of a suite")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("create")
+                .about("records the pool and dists of the suite as a named snapshot")
+                .arg(Arg::with_name("name").required(true)))
+            .subcommand(SubCommand::with_name("list")
+                .about("lists the snapshots of the suite"))
+            .subcommand(SubCommand::with_name("publish")
+                .about("publishes a snapshot as dists/<suite>-<name>")
+                .arg(Arg::with_name("name").required(true)))
         ).subcommand(SubCommand::with_name("update")
             .about("Updates direct download-based packages in the configuration")
             .alias("u")
+        ).subcommand(SubCommand::with_name("verify")
+            .about("Checks that the published dists, signatures, and pool are consistent, and prints a JSON report")
         ).subcommand(SubCommand::with_name("migrate")
             .about("Moves a package from one component to another, updating both components in the process")
             .alias("m")
@@ -234,10 +293,23 @@ fn apply_config(mut config: Config, matches: &ArgMatches) {
                 .build()
                 .generate();
         },
+        Action::Check => {
+            if let Err(why) = repo::check_installability(&config) {
+                error!("installability check failed: {}", why);
+                exit(1);
+            }
+        },
         Action::Clean => {
             Repo::prepare(config, Packages::All).clean();
         },
-        Action::Dist => {
+        Action::Dist(clear_cache) => {
+            if clear_cache {
+                if let Err(why) = repo::clear_dist_cache(&config.archive) {
+                    error!("failed to clear dist cache: {}", why);
+                    exit(1);
+                }
+            }
+
             Repo::prepare(config, Packages::All).generate();
         },
         Action::Fetch(key) => match config.fetch(&key) {
@@ -248,6 +320,12 @@ fn apply_config(mut config: Config, matches: &ArgMatches) {
             }
         },
         Action::FetchConfig => println!("{}: {:#?}", config.path.display(), &config),
+        Action::List(component, arch, pattern, json) => {
+            if let Err(why) = repo::list(&config, component, arch, pattern, json) {
+                error!("failed to list packages: {}", why);
+                exit(1);
+            }
+        },
         Action::Migrate(packages, from_component, to_component) => {
             if let Err(why) = repo::migrate(&config, &packages, from_component, to_component) {
                 error!("migration failed: {}", why);
@@ -257,9 +335,51 @@ fn apply_config(mut config: Config, matches: &ArgMatches) {
         Action::Pool => {
             Repo::prepare(config, Packages::All).download();
         },
+        Action::RDepends(package, json) => {
+            if let Err(why) = repo::rdepends(&config, package, json) {
+                error!("failed to list reverse dependencies: {}", why);
+                exit(1);
+            }
+        },
+        Action::Refresh => {
+            if let Err(why) = repo::refresh(&config) {
+                error!("failed to refresh release files: {}", why);
+                exit(1);
+            }
+        },
         Action::Remove(packages) => {
             Repo::prepare(config, Packages::Select(&packages, false)).remove();
         },
+        Action::Rollback => {
+            if let Err(why) = repo::rollback(&config) {
+                error!("rollback failed: {}", why);
+                exit(1);
+            }
+        },
+        Action::Show(package, json) => {
+            if let Err(why) = repo::show(&config, package, json) {
+                error!("failed to show package: {}", why);
+                exit(1);
+            }
+        },
+        Action::SnapshotCreate(name) => {
+            if let Err(why) = repo::create_snapshot(&config, name) {
+                error!("failed to create snapshot: {}", why);
+                exit(1);
+            }
+        },
+        Action::SnapshotList => {
+            if let Err(why) = repo::list_snapshots(&config) {
+                error!("failed to list snapshots: {}", why);
+                exit(1);
+            }
+        },
+        Action::SnapshotPublish(name) => {
+            if let Err(why) = repo::publish_snapshot(&config, name) {
+                error!("failed to publish snapshot: {}", why);
+                exit(1);
+            }
+        },
         Action::Update(key, value) => match config.update(key, value.to_owned()) {
             Ok(()) => match config.write_to_disk() {
                 Ok(()) => info!("successfully wrote config changes to disk"),
@@ -278,6 +398,21 @@ fn apply_config(mut config: Config, matches: &ArgMatches) {
                 .download()
                 .build()
                 .generate();
+        },
+        Action::Verify => {
+            let report = repo::verify(&config);
+            match serde_json::to_string(&report) {
+                Ok(json) => println!("{}", json),
+                Err(why) => {
+                    error!("failed to serialize verification report: {}", why);
+                    exit(1);
+                }
+            }
+
+            if !report.passed {
+                error!("verification of {} failed", report.suite);
+                exit(1);
+            }
         }
     }
 }
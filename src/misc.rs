@@ -3,17 +3,14 @@ use std::fs::{self, File};
 use std::io::{self, Error, ErrorKind, Read, Write};
 use std::os::unix::ffi::OsStringExt;
 use std::path::Path;
-use debian::DEB_SOURCE_EXTENSIONS;
+use debian::{DEB_INSTALLER_EXTENSION, DEB_SOURCE_EXTENSIONS};
 
 use libc;
 use walkdir::{DirEntry, WalkDir};
 
 pub const INCLUDE_DDEB: u8 = 1;
 pub const INCLUDE_SRCS: u8 = 2;
-
-pub static ARCHITECTURES: [&str; 10] = [
-    "amd64", "i386", "arm64", "armel", "armhf", "mips", "mipsel", "mips64el", "ppc64el", "s390x",
-];
+pub const INCLUDE_UDEB: u8 = 4;
 
 pub fn filename_from_url(url: &str) -> &str {
     &url[url.rfind('/').map_or(0, |x| x + 1)..]
@@ -59,6 +56,8 @@ pub fn is_deb(entry: &DirEntry, flags: u8) -> bool {
     entry.file_name().to_str().map_or(false, |e| {
         e.ends_with(".deb") || {
             if flags & INCLUDE_DDEB != 0 { e.ends_with(".ddeb") } else { false }
+        } || {
+            if flags & INCLUDE_UDEB != 0 { e.ends_with(DEB_INSTALLER_EXTENSION) } else { false }
         } || {
             if flags & INCLUDE_SRCS != 0 {
                 DEB_SOURCE_EXTENSIONS.into_iter().any(|ext| e.ends_with(ext))
@@ -69,11 +68,11 @@ pub fn is_deb(entry: &DirEntry, flags: u8) -> bool {
     })
 }
 
-pub fn walk_debs(path: &Path, ddeb: bool) -> Box<Iterator<Item = DirEntry>> {
+pub fn walk_debs(path: &Path, flags: u8) -> Box<Iterator<Item = DirEntry>> {
     Box::new(
         WalkDir::new(path)
             .into_iter()
-            .filter_entry(move |e| if e.path().is_dir() { true } else { is_deb(e, ddeb as u8) })
+            .filter_entry(move |e| if e.path().is_dir() { true } else { is_deb(e, flags) })
             .flat_map(|e| e.ok())
     )
 }
@@ -92,14 +91,17 @@ pub fn match_deb(entry: &DirEntry, packages: &[String]) -> Option<(String, usize
     })
 }
 
-pub fn copy_here<S>(source: S) -> io::Result<()>
+/// Copies each file within the source directory into the destination directory.
+pub fn copy_into<S, D>(source: S, destination: D) -> io::Result<()>
     where S: AsRef<Path>,
+          D: AsRef<Path>,
 {
     for entry in source.as_ref().read_dir()? {
         let entry = entry?;
         if entry.path().is_file() {
             let source = &entry.path();
-            if let Some(dest) = source.file_name() {
+            if let Some(name) = source.file_name() {
+                let dest = destination.as_ref().join(name);
                 eprintln!("copying {:?} to {:?}", source, dest);
                 io::copy(&mut File::open(source)?, &mut File::create(dest)?)?;
             }
@@ -118,8 +120,43 @@ pub fn unlink(link: &Path) -> io::Result<()> {
         })
 }
 
-pub fn get_arch_from_stem(stem: &str) -> &str {
-    if let Some(arch) = ARCHITECTURES.iter().find(|&x| stem.ends_with(x)) {
+/// Atomically exchanges two paths, via `renameat2` with `RENAME_EXCHANGE`.
+pub fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    const RENAME_EXCHANGE: libc::c_uint = 1 << 1;
+
+    let to_cstring = |path: &Path| {
+        CString::new(path.to_path_buf().into_os_string().into_vec())
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidInput, format!("{}", why)))
+    };
+
+    let (a, b) = (to_cstring(a)?, to_cstring(b)?);
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            RENAME_EXCHANGE
+        )
+    };
+
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error())
+    }
+}
+
+/// Determines the architecture of an archive from its file stem, preferring the architectures
+/// which the suite is configured with, of which the longest match wins.
+pub fn get_arch_from_stem<'a>(stem: &'a str, architectures: &'a [String]) -> &'a str {
+    let known = architectures.iter()
+        .map(|arch| arch.as_str())
+        .chain(Some("all"))
+        .filter(|arch| stem.ends_with(arch))
+        .max_by_key(|arch| arch.len());
+
+    if let Some(arch) = known {
         return arch;
     }
 
@@ -0,0 +1,156 @@
+use subprocess::Exec;
+use super::{file_name, open_log, quote, run, run_prebuild, Backend, BuildJob};
+use super::super::BuildError;
+
+/// Builds within a container of an image, using a rootless runtime such as podman.
+///
+/// The output directory is mounted at `/build`, and the build dependencies of the source are
+/// installed within the container by apt before `dpkg-buildpackage` is run. The image should
+/// therefore be of the suite which is being built.
+pub struct Container {
+    image: String,
+    runtime: String,
+}
+
+impl Container {
+    pub fn new(image: String, runtime: String) -> Self {
+        Container { image, runtime }
+    }
+}
+
+impl Backend for Container {
+    fn build(&self, job: &BuildJob) -> Result<(), BuildError> {
+        let log = open_log(job)?;
+        run_prebuild(job, "container", &log)?;
+
+        let mut command = Exec::cmd(&self.runtime)
+            .args(&["run", "--rm", "-v"])
+            .arg(format!("{}:/build", job.output.display()));
+
+        // A source directory is mounted beside the packages, as they are written to its parent.
+        // The files of a dsc are mounted elsewhere, and the dsc is extracted beside the packages.
+        let (source, mount) = match job.path.parent() {
+            Some(parent) if job.is_dsc() => (parent, "/source".to_owned()),
+            _ => (job.path, ["/build/", &file_name(job.path)].concat())
+        };
+
+        command = command.arg("-v").arg(format!("{}:{}", source.display(), mount));
+
+        for package in &job.extra_packages {
+            command = command.arg("-v")
+                .arg(format!("{}:/extra/{}:ro", package.display(), file_name(package)));
+        }
+
+        for key in job.extra_keys {
+            command = command.arg("-v")
+                .arg(format!("{}:/etc/apt/trusted.gpg.d/{}:ro", key.display(), file_name(key)));
+        }
+
+        command = command.arg(&self.image)
+            .args(&["sh", "-c"])
+            .arg(script(job, &mount)?);
+
+        run("container", job, command, &log)
+    }
+}
+
+/// The script which installs the build dependencies and builds the source within the container.
+fn script(job: &BuildJob, mount: &str) -> Result<String, BuildError> {
+    let arch = quote(job.arch);
+    let mut script = String::from("set -e\nexport DEBIAN_FRONTEND=noninteractive\n");
+
+    for repo in job.extra_repositories {
+        script.push_str(&format!("echo {} >> /etc/apt/sources.list.d/debrep.list\n", quote(repo)));
+    }
+
+    script.push_str(&format!(
+        "[ \"$(dpkg --print-architecture)\" = {0} ] || dpkg --add-architecture {0}\n\
+         apt-get update\n\
+         apt-get install -y --no-install-recommends build-essential dpkg-dev",
+        arch
+    ));
+
+    for package in &job.extra_packages {
+        script.push_str(&[" /extra/", &file_name(package)].concat());
+    }
+
+    script.push('\n');
+
+    let directory = if job.is_dsc() {
+        let directory = format!("/build/{}-{}-src", job.package, job.arch);
+        script.push_str(&format!(
+            "rm -rf {0}\ndpkg-source -x {1} {0}\n",
+            quote(&directory),
+            quote(&[mount, "/", &file_name(job.path)].concat())
+        ));
+        directory
+    } else {
+        mount.to_owned()
+    };
+
+    script.push_str(&format!(
+        "cd {}\napt-get build-dep -y --host-architecture {} ./\n",
+        quote(&directory),
+        arch
+    ));
+
+    for cmd in job.starting_build {
+        script.push_str(&job.expand(cmd, "container")?);
+        script.push('\n');
+    }
+
+    script.push_str(&format!(
+        "dpkg-buildpackage -us -uc --host-arch={}{}\n",
+        arch,
+        if job.is_dsc() { " -b" } else { "" }
+    ));
+
+    if job.is_dsc() {
+        script.push_str(&format!("cd /build\nrm -rf {}\n", quote(&directory)));
+    }
+
+    Ok(script)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use super::super::tests::job;
+
+    #[test]
+    fn source_directory_script() {
+        let commands = ["echo '%a'".to_owned()];
+        let repos = ["deb http://example.com bionic main".to_owned()];
+        let mut job = job(Path::new("/project/build/bionic/foo"), &commands);
+        job.extra_repositories = &repos;
+
+        assert_eq!(script(&job, "/build/foo").unwrap(), "set -e\n\
+            export DEBIAN_FRONTEND=noninteractive\n\
+            echo 'deb http://example.com bionic main' >> /etc/apt/sources.list.d/debrep.list\n\
+            [ \"$(dpkg --print-architecture)\" = 'armhf' ] || dpkg --add-architecture 'armhf'\n\
+            apt-get update\n\
+            apt-get install -y --no-install-recommends build-essential dpkg-dev /extra/libbar_1.0_armhf.deb\n\
+            cd '/build/foo'\n\
+            apt-get build-dep -y --host-architecture 'armhf' ./\n\
+            echo 'armhf'\n\
+            dpkg-buildpackage -us -uc --host-arch='armhf'\n");
+    }
+
+    #[test]
+    fn dsc_script() {
+        let mut job = job(Path::new("/project/build/bionic/foo/foo_1.0.dsc"), &[]);
+        job.extra_packages = Vec::new();
+
+        let script = script(&job, "/source").unwrap();
+        assert!(script.contains("rm -rf '/build/foo-armhf-src'\ndpkg-source -x '/source/foo_1.0.dsc' '/build/foo-armhf-src'\n"));
+        assert!(script.contains("cd '/build/foo-armhf-src'\n"));
+        assert!(script.ends_with("dpkg-buildpackage -us -uc --host-arch='armhf' -b\ncd /build\nrm -rf '/build/foo-armhf-src'\n"));
+    }
+
+    #[test]
+    fn chroot_escapes() {
+        let commands = ["%SBUILD_CHROOT_EXEC apt-get update".to_owned()];
+        assert!(script(&job(Path::new("/project/build/bionic/foo"), &commands), "/build/foo").is_err());
+    }
+}
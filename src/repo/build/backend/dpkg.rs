@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::PathBuf;
+use subprocess::Exec;
+use super::{open_log, run, run_prebuild, Backend, BuildJob};
+use super::super::BuildError;
+
+/// Builds on the host with `dpkg-buildpackage`.
+///
+/// The host is not modified by the build, so the build dependencies of the source, including
+/// any extra packages and repositories, must already be installed on the host.
+pub struct Dpkg;
+
+impl Backend for Dpkg {
+    fn build(&self, job: &BuildJob) -> Result<(), BuildError> {
+        if !job.extra_packages.is_empty() || !job.extra_repositories.is_empty() {
+            warn!(
+                "{} is built on the host, which must already have its extra packages and repositories",
+                job.package
+            );
+        }
+
+        let log = open_log(job)?;
+        run_prebuild(job, "dpkg", &log)?;
+
+        // The packages are written to the parent of the directory which they are built from.
+        let directory = if job.is_dsc() {
+            let directory = job.output.join(format!("{}-{}-src", job.package, job.arch));
+            if directory.exists() {
+                fs::remove_dir_all(&directory)
+                    .map_err(|why| BuildError::Directory { path: directory.clone(), why })?;
+            }
+
+            let command = Exec::cmd("dpkg-source").arg("-x").arg(job.path).arg(&directory);
+            run("dpkg-source", job, command, &log)?;
+            directory
+        } else {
+            PathBuf::from(job.path)
+        };
+
+        // The host is the build environment.
+        for cmd in job.starting_build {
+            run("sh", job, Exec::shell(job.expand(cmd, "dpkg")?).cwd(&directory), &log)?;
+        }
+
+        let mut command = Exec::cmd("dpkg-buildpackage")
+            .args(&["-us", "-uc", &format!("--host-arch={}", job.arch)])
+            .cwd(&directory);
+
+        // The source package of a dsc has already been built.
+        if job.is_dsc() {
+            command = command.arg("-b");
+        }
+
+        let result = run("dpkg-buildpackage", job, command, &log);
+
+        if job.is_dsc() {
+            let _ = fs::remove_dir_all(&directory);
+        }
+
+        result
+    }
+}
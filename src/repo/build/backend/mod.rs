@@ -0,0 +1,207 @@
+//! The backends which build a source into packages.
+//!
+//! Each backend is given a `BuildJob`, which describes the source to build independently of the
+//! tool that builds it. The extra packages, extra repositories, and commands that sbuild accepts
+//! as arguments are mapped by each backend onto whatever its own tool supports.
+//!
+//! The commands keep the meaning that they have for sbuild, and may use its `%` escapes. sbuild
+//! expands the escapes itself. The other backends expand `%%`, and `%a` or `%SBUILD_HOST_ARCH`,
+//! and reject the escapes which refer to the chroot or files of sbuild.
+
+mod container;
+mod dpkg;
+mod pbuilder;
+mod sbuild;
+
+pub use self::container::Container;
+pub use self::dpkg::Dpkg;
+pub use self::pbuilder::Pbuilder;
+pub use self::sbuild::Sbuild;
+
+use config::Builder;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use subprocess::{Exec, Redirection};
+use super::BuildError;
+
+/// A source to build for one architecture.
+pub struct BuildJob<'a> {
+    pub package: &'a str,
+    pub suite: &'a str,
+    pub arch: &'a str,
+    /// The directory of the source, or the path of its `.dsc` file.
+    pub path: &'a Path,
+    /// The directory which the packages are written to, which belongs to this job alone.
+    pub output: PathBuf,
+    /// The file which the output of the build is logged to.
+    pub log: PathBuf,
+    /// Archives from the pool which must be installed to satisfy the build dependencies.
+    pub extra_packages: Vec<PathBuf>,
+    /// Sources list entries of repositories which build dependencies may be fetched from.
+    pub extra_repositories: &'a [String],
+    /// Keys which the extra repositories are signed with.
+    pub extra_keys: &'a [PathBuf],
+    /// Commands which are run on the host, within the output directory, before the build
+    /// environment is prepared, as sbuild's `--pre-build-commands` are.
+    pub prebuild: &'a [String],
+    /// Commands which are run within the build environment, after the build dependencies are
+    /// installed and before the build starts, as sbuild's `--starting-build-commands` are.
+    pub starting_build: &'a [String],
+}
+
+impl<'a> BuildJob<'a> {
+    pub fn is_dsc(&self) -> bool {
+        self.path.extension().map_or(false, |ext| ext == "dsc")
+    }
+
+    /// Expands the sbuild escapes of a command, for a backend other than sbuild.
+    pub fn expand(&self, command: &str, backend: &'static str) -> Result<String, BuildError> {
+        let mut expanded = String::with_capacity(command.len());
+        let mut rest = command;
+        while let Some(pos) = rest.find('%') {
+            expanded.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+
+            if rest.starts_with('%') {
+                expanded.push('%');
+                rest = &rest[1..];
+            } else if rest.starts_with("SBUILD_HOST_ARCH") {
+                expanded.push_str(self.arch);
+                rest = &rest[16..];
+            } else if rest.starts_with('a') {
+                expanded.push_str(self.arch);
+                rest = &rest[1..];
+            } else if rest.starts_with("SBUILD_") || rest.starts_with(SBUILD_ESCAPES) {
+                return Err(BuildError::UnsupportedEscape { command: command.to_owned(), backend });
+            } else {
+                expanded.push('%');
+            }
+        }
+
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+}
+
+/// The short escapes of sbuild which refer to its chroot, or to the files of its build.
+const SBUILD_ESCAPES: &[char] = &['b', 'c', 'd', 'e', 'p', 'r', 's'];
+
+pub trait Backend: Send + Sync {
+    /// Builds the source, writing its packages to the output directory of the job.
+    fn build(&self, job: &BuildJob) -> Result<(), BuildError>;
+}
+
+/// Constructs the backend which a source is configured to be built with.
+///
+/// Paths within the config are relative to the root of the project.
+pub fn from_config(builder: &Builder, root: &Path) -> Box<Backend> {
+    match *builder {
+        Builder::Sbuild => Box::new(Sbuild),
+        Builder::Pbuilder { ref basetgz } => {
+            Box::new(Pbuilder::new(basetgz.as_ref().map(|basetgz| root.join(basetgz))))
+        }
+        Builder::Dpkg => Box::new(Dpkg),
+        Builder::Container { ref image, ref runtime } => Box::new(Container::new(
+            image.clone(),
+            runtime.clone().unwrap_or_else(|| "podman".to_owned())
+        )),
+    }
+}
+
+/// Creates the log of the job, truncating the log of any previous build.
+fn open_log(job: &BuildJob) -> Result<File, BuildError> {
+    fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&job.log)
+        .map_err(|why| BuildError::Open { file: job.log.clone(), why })
+}
+
+/// Runs the command with its output appended to the log, failing if it exits unsuccessfully.
+fn run(cmd: &'static str, job: &BuildJob, command: Exec, log: &File) -> Result<(), BuildError> {
+    let log = log.try_clone()
+        .map_err(|why| BuildError::Open { file: job.log.clone(), why })?;
+
+    let command = command
+        .stdout(Redirection::Merge)
+        .stderr(Redirection::File(log));
+
+    debug!("executing {:#?}", command);
+
+    let exit_status = command.join()
+        .map_err(|why| BuildError::Command {
+            cmd,
+            why: io::Error::new(
+                io::ErrorKind::Other,
+                format!("{:?}", why)
+            )
+        })?;
+
+    if exit_status.success() {
+        Ok(())
+    } else {
+        Err(BuildError::Build {
+            package: job.package.to_owned(),
+            reason: exit_status
+        })
+    }
+}
+
+/// Runs the prebuild commands of the job on the host, within the output directory.
+fn run_prebuild(job: &BuildJob, backend: &'static str, log: &File) -> Result<(), BuildError> {
+    for cmd in job.prebuild {
+        let cmd = job.expand(cmd, backend)?;
+        run("sh", job, Exec::shell(cmd).cwd(&job.output), log)?;
+    }
+
+    Ok(())
+}
+
+/// Quotes an argument so that a shell reads it as a single word.
+fn quote(arg: &str) -> String {
+    ["'", &arg.replace('\'', r"'\''"), "'"].concat()
+}
+
+/// The name of a file, which is used to place it at a known location within a chroot or container.
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub fn job<'a>(path: &'a Path, commands: &'a [String]) -> BuildJob<'a> {
+        BuildJob {
+            package: "foo",
+            suite: "bionic",
+            arch: "armhf",
+            path,
+            output: PathBuf::from("/project/build/bionic"),
+            log: PathBuf::from("/project/logs/bionic/foo-armhf"),
+            extra_packages: vec![PathBuf::from("/project/repo/pool/bionic/main/binary-armhf/l/libbar/libbar_1.0_armhf.deb")],
+            extra_repositories: &[],
+            extra_keys: &[],
+            prebuild: commands,
+            starting_build: commands,
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let job = job(Path::new("/project/build/bionic/foo"), &[]);
+        assert_eq!(job.expand("echo %a %SBUILD_HOST_ARCH 100%%", "dpkg").unwrap(), "echo armhf armhf 100%");
+        assert_eq!(job.expand("date +%Y", "dpkg").unwrap(), "date +%Y");
+        assert!(job.expand("%e apt-get update", "dpkg").is_err());
+        assert!(job.expand("ls %SBUILD_CHROOT_DIR", "dpkg").is_err());
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("foo"), "'foo'");
+        assert_eq!(quote("deb http://a b c"), "'deb http://a b c'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
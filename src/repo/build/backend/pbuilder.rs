@@ -0,0 +1,149 @@
+use std::{env, fs};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use subprocess::Exec;
+use super::{file_name, open_log, quote, run, run_prebuild, Backend, BuildJob};
+use super::super::BuildError;
+use tempfile;
+
+/// Builds within a pbuilder chroot.
+///
+/// A dsc is built with `pbuilder build`, and a source directory with `pdebuild`. The extra
+/// packages and keys of the job are installed by a `D` hook, which pbuilder runs within the
+/// chroot before the build dependencies are installed, and the starting build commands are run
+/// by an `A` hook, after they are installed.
+pub struct Pbuilder {
+    basetgz: Option<PathBuf>,
+}
+
+impl Pbuilder {
+    pub fn new(basetgz: Option<PathBuf>) -> Self {
+        Pbuilder { basetgz }
+    }
+}
+
+impl Backend for Pbuilder {
+    fn build(&self, job: &BuildJob) -> Result<(), BuildError> {
+        let log = open_log(job)?;
+        run_prebuild(job, "pbuilder", &log)?;
+
+        let hooks = tempfile::tempdir()
+            .map_err(|why| BuildError::Directory { path: env::temp_dir(), why })?;
+
+        write_hook(&hooks.path().join("D10debrep"), &setup_hook(job))?;
+        write_hook(&hooks.path().join("A10debrep"), &starting_build_hook(job)?)?;
+
+        let mut options = vec![
+            "--host-arch".to_owned(), job.arch.to_owned(),
+            "--hookdir".to_owned(), hooks.path().display().to_string(),
+        ];
+
+        if let Some(ref basetgz) = self.basetgz {
+            options.push("--basetgz".to_owned());
+            options.push(basetgz.display().to_string());
+        }
+
+        if !job.extra_repositories.is_empty() {
+            options.push("--othermirror".to_owned());
+            options.push(job.extra_repositories.join(" | "));
+        }
+
+        // The extra packages and keys are read by the hooks from their paths on the host.
+        let mut mounts = job.extra_packages.iter()
+            .chain(job.extra_keys)
+            .filter_map(|path| path.parent())
+            .map(|path| path.display().to_string())
+            .collect::<Vec<String>>();
+
+        mounts.sort();
+        mounts.dedup();
+
+        if !mounts.is_empty() {
+            options.push("--bindmounts".to_owned());
+            options.push(mounts.join(" "));
+        }
+
+        let command = if job.is_dsc() {
+            Exec::cmd("sudo")
+                .args(&["pbuilder", "build", "--buildresult"])
+                .arg(&job.output)
+                .args(&options)
+                .arg(job.path)
+        } else {
+            Exec::cmd("pdebuild")
+                .arg("--buildresult")
+                .arg(&job.output)
+                .arg("--")
+                .args(&options)
+                .cwd(job.path)
+        };
+
+        run("pbuilder", job, command, &log)
+    }
+}
+
+/// Trusts the extra keys, and installs the extra packages.
+fn setup_hook(job: &BuildJob) -> String {
+    let mut script = String::from("#!/bin/sh\nset -e\n");
+
+    for key in job.extra_keys {
+        script.push_str(&format!(
+            "cp {} /etc/apt/trusted.gpg.d/{}\n",
+            quote(&key.display().to_string()),
+            quote(&file_name(key))
+        ));
+    }
+
+    if !job.extra_keys.is_empty() {
+        script.push_str("apt-get update\n");
+    }
+
+    if !job.extra_packages.is_empty() {
+        script.push_str("apt-get install -y");
+        for package in &job.extra_packages {
+            script.push(' ');
+            script.push_str(&quote(&package.display().to_string()));
+        }
+        script.push('\n');
+    }
+
+    script
+}
+
+fn starting_build_hook(job: &BuildJob) -> Result<String, BuildError> {
+    let mut script = String::from("#!/bin/sh\nset -e\n");
+    for cmd in job.starting_build {
+        script.push_str(&job.expand(cmd, "pbuilder")?);
+        script.push('\n');
+    }
+
+    Ok(script)
+}
+
+fn write_hook(path: &Path, script: &str) -> Result<(), BuildError> {
+    fs::write(path, script)
+        .and_then(|_| fs::set_permissions(path, fs::Permissions::from_mode(0o755)))
+        .map_err(|why| BuildError::Hook { path: path.to_path_buf(), why })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::job;
+
+    #[test]
+    fn hooks() {
+        let commands = ["echo %a".to_owned()];
+        let keys = [PathBuf::from("/project/keys/extra.asc")];
+        let mut job = job(Path::new("/project/build/bionic/foo"), &commands);
+        job.extra_keys = &keys;
+
+        assert_eq!(setup_hook(&job), "#!/bin/sh\n\
+            set -e\n\
+            cp '/project/keys/extra.asc' /etc/apt/trusted.gpg.d/'extra.asc'\n\
+            apt-get update\n\
+            apt-get install -y '/project/repo/pool/bionic/main/binary-armhf/l/libbar/libbar_1.0_armhf.deb'\n");
+
+        assert_eq!(starting_build_hook(&job).unwrap(), "#!/bin/sh\nset -e\necho armhf\n");
+    }
+}
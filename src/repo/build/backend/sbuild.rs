@@ -0,0 +1,43 @@
+use subprocess::Exec;
+use super::{open_log, run, Backend, BuildJob};
+use super::super::BuildError;
+
+/// Builds within the sbuild chroot of the suite.
+pub struct Sbuild;
+
+impl Backend for Sbuild {
+    fn build(&self, job: &BuildJob) -> Result<(), BuildError> {
+        let log = open_log(job)?;
+        let mut command = Exec::cmd("sbuild")
+            .args(&[
+                "-v", "--log-external-command-output", "--log-external-command-error",
+                &format!("--host={}", job.arch),
+                // "--dpkg-source-opt=-Zgzip", // Use this when testing
+                "-d", job.suite
+            ])
+            // The packages are written to the output directory of the job.
+            .cwd(&job.output);
+
+        for package in &job.extra_packages {
+            command = command.arg(format!("--extra-package={}", package.display()));
+        }
+
+        for key in job.extra_keys {
+            command = command.arg(format!("--extra-repository-key={}", key.display()));
+        }
+
+        for repo in job.extra_repositories {
+            command = command.arg(["--extra-repository=", repo].concat());
+        }
+
+        for cmd in job.prebuild {
+            command = command.arg(["--pre-build-commands=", cmd].concat());
+        }
+
+        for cmd in job.starting_build {
+            command = command.arg(["--starting-build-commands=", cmd].concat());
+        }
+
+        run("sbuild", job, command.arg(job.path), &log)
+    }
+}
@@ -1,7 +1,9 @@
 mod artifacts;
+mod backend;
 mod extract;
 mod metapackages;
 mod rsync;
+mod schedule;
 
 use command::Command;
 use config::{Config, DebianPath, Direct, Source, SourceLocation};
@@ -11,17 +13,20 @@ use debian;
 use glob::glob;
 use misc;
 use self::artifacts::{link_artifact, LinkedArtifact, LinkError};
+use self::backend::BuildJob;
 use self::rsync::rsync;
+use self::schedule::BuildGraph;
 use std::cmp::Ordering;
 use std::env;
 use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use subprocess::{self, Exec, Redirection};
+use subprocess;
 use super::pool::{mv_to_pool, KEEP_SOURCE};
 use super::super::SHARED_ASSETS;
 use super::version::{changelog, git};
+use tempfile;
 use walkdir::WalkDir;
 
 pub fn all(config: &Config) {
@@ -31,32 +36,15 @@ pub fn all(config: &Config) {
 
     if let Some(ref sources) = config.source {
         migrate_to_pool(config, sources.iter());
-        let build_path = ["build/", &config.archive].concat();
-        for source in sources {
-            if let Err(why) = build(config, source, &pwd, suite, component, false) {
-                error!("package '{}' failed to build: {}", source.name, why);
-                exit(1);
-            }
-
-            if let Err(why) = mv_to_pool(
-                &build_path,
-                &config.archive,
-                &config.default_component,
-                if source.keep_source { KEEP_SOURCE } else { 0 },
-                Some(&source.name)
-            ) {
-                error!("package '{}' failed to migrate to pool: {}", source.name, why);
-                exit(1);
-            }
-        }
+        build_sources(config, sources.iter().collect(), &pwd, false);
     }
 
-    if let Err(why) = repackage_binaries(config.direct.as_ref(), suite, component) {
+    if let Err(why) = repackage_binaries(config.direct.as_ref(), suite, component, &config.all_architectures()) {
         error!("binary repackage failure: {}", why);
         exit(1);
     }
 
-    if let Err(why) = metapackages::generate(&config.archive, &config.default_component) {
+    if let Err(why) = metapackages::generate(&config.archive, &config.default_component, &config.all_architectures()) {
         error!("metapackage generation failed: {}", why);
         exit(1);
     }
@@ -64,46 +52,100 @@ pub fn all(config: &Config) {
 
 pub fn packages(config: &Config, packages: &[&str], force: bool) {
     let pwd = env::current_dir().unwrap();
-    let mut built = 0;
     match config.source.as_ref() {
         Some(items) => {
-            let sources = items.into_iter()
+            let sources = items.iter()
                 .filter(|item| packages.contains(&item.name.as_str()))
                 .collect::<Vec<&Source>>();
 
             migrate_to_pool(config, sources.iter().cloned());
-            let build_path = ["build/", &config.archive].concat();
-            for source in &sources {
-                if let Err(why) = build(config, source, &pwd, &config.archive, &config.default_component, force) {
-                    error!("package '{}' failed to build: {}", source.name, why);
-                    exit(1);
-                }
+            build_sources(config, sources, &pwd, force);
+        },
+        None => warn!("no packages built")
+    }
+}
 
-                if let Err(why) = mv_to_pool(
-                    &build_path,
-                    &config.archive,
-                    &config.default_component,
-                    if source.keep_source { KEEP_SOURCE } else { 0 },
-                    Some(&source.name)
-                ) {
-                    error!("package '{}' failed to migrate to pool: {}", source.name, why);
-                    exit(1);
-                }
+/// Builds the sources in the order of their dependencies, moving the packages of each source to
+/// the pool as soon as it has been built, so that the sources which depend upon it may be built.
+///
+/// Every source that can be built is built before exiting, if any source failed to build.
+fn build_sources(config: &Config, sources: Vec<&Source>, pwd: &Path, force: bool) {
+    let binaries = sources.iter()
+        .map(|source| binaries_of(pwd, &config.archive, source))
+        .collect::<Vec<Vec<String>>>();
+
+    let graph = match BuildGraph::new(sources, &binaries) {
+        Ok(graph) => graph,
+        Err(why) => {
+            error!("unable to order the sources to build: {}", why);
+            exit(1);
+        }
+    };
 
-                built += 1;
-                if built == packages.len() {
-                    break
-                }
+    let architectures = config.all_architectures();
+    let failures = graph.build(config.build_jobs.unwrap_or(1), |source| {
+        build(config, source, pwd, &config.archive, &config.default_component, force)?;
+
+        // Every file which the build wrote to its output directories belongs to the source.
+        for arch in &config.architectures {
+            let output = job_output(pwd, &config.archive, &source.name, arch);
+            if !output.exists() {
+                continue
             }
-        },
-        None => warn!("no packages built")
+
+            mv_to_pool(
+                &output,
+                &config.archive,
+                &config.default_component,
+                &architectures,
+                if source.keep_source { KEEP_SOURCE } else { 0 },
+                None
+            ).and_then(|_| fs::remove_dir_all(&output))
+                .map_err(|why| BuildError::Pool { package: source.name.clone(), why })?;
+        }
+
+        Ok(())
+    });
+
+    for &(ref package, ref why) in &failures {
+        error!("package '{}' failed to build: {}", package, why);
     }
+
+    if !failures.is_empty() {
+        exit(1);
+    }
+}
+
+/// The names of the binary packages which the source builds, so that the sources which depend
+/// upon them are built after it.
+///
+/// The names are read from the `debian/control` file of the downloaded source, or from the
+/// `Binary` field of its dsc. Sources which are extracted at build time only provide their own name.
+fn binaries_of(pwd: &Path, suite: &str, source: &Source) -> Vec<String> {
+    let directory = pwd.join(["build/", suite, "/", &source.name].concat());
+
+    let control = misc::read_to_string(directory.join("debian/control")).ok().map(|control| {
+        debian::deb822::parse(&control).into_iter()
+            .filter_map(|mut paragraph| paragraph.remove("Package"))
+            .collect::<Vec<String>>()
+    });
+
+    let dsc = || directory.read_dir().ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.extension().map_or(false, |ext| ext == "dsc"))
+        .and_then(|dsc| misc::read_to_string(dsc).ok())
+        .and_then(|dsc| debian::deb822::parse(&dsc).into_iter().next())
+        .and_then(|mut paragraph| paragraph.remove("Binary"))
+        .map(|binary| binary.split(',').map(|name| name.trim().to_owned()).filter(|name| !name.is_empty()).collect());
+
+    control.or_else(dsc).unwrap_or_default()
 }
 
-fn repackage_binaries(packages: Option<&Vec<Direct>>, suite: &str, component: &str) -> io::Result<()> {
+fn repackage_binaries(packages: Option<&Vec<Direct>>, suite: &str, component: &str, architectures: &[String]) -> io::Result<()> {
     if let Some(packages) = packages {
         for package in packages {
-            for destinations in package.get_destinations(suite, component).unwrap() {
+            for destinations in package.get_destinations(suite, component, architectures).unwrap() {
                 let pool = &destinations.pool;
                 if let Some(&(ref files, ref source_deb)) = destinations.assets.as_ref() {
                     if needs_to_repackage(source_deb, files, pool)? {
@@ -186,6 +228,7 @@ fn migrate_to_pool<'a , I: Iterator<Item = &'a Source>>(config: &Config, sources
             &build_path,
             &config.archive,
             &config.default_component,
+            &config.all_architectures(),
             if source.keep_source { KEEP_SOURCE } else { 0 },
             Some(&source.name)
         ) {
@@ -209,6 +252,10 @@ pub enum BuildError {
     Debchange { why: io::Error },
     #[fail(display = "failed to create missing debian files for {:?}: {}", path, why)]
     DebFile { path: PathBuf, why: io::Error },
+    #[fail(display = "sources depend upon each other in a cycle: {}", packages)]
+    DependencyCycle { packages: String },
+    #[fail(display = "not built, because {} failed to build", dependency)]
+    DependencyFailed { dependency: String },
     #[fail(display = "failed to create directory for {:?}: {}", path, why)]
     Directory { path: PathBuf, why: io::Error },
     #[fail(display = "failed to move dsc files: {:?}", why)]
@@ -219,6 +266,8 @@ pub enum BuildError {
     GitBranch { package: String, branch: String, why: io::Error },
     #[fail(display = "failed to get git commit for {}: {}", package, why)]
     GitCommit { package: String, why: io::Error },
+    #[fail(display = "failed to write build hook at {:?}: {}", path, why)]
+    Hook { path: PathBuf, why: io::Error },
     #[fail(display = "failed to link {:?} to {:?}: {}", src, dst, why)]
     Link { src: PathBuf, dst: PathBuf, why: io::Error },
     #[fail(display = "failed due to missing dependencies")]
@@ -227,12 +276,16 @@ pub enum BuildError {
     NoChangelogVersion { package: String },
     #[fail(display = "failed to open file at {:?}: {}", file, why)]
     Open { file: PathBuf, why: io::Error },
+    #[fail(display = "failed to move {} to the pool: {}", package, why)]
+    Pool { package: String, why: io::Error },
     #[fail(display = "failed to read file at {:?}: {}", file, why)]
     Read { file: PathBuf, why: io::Error },
     #[fail(display = "failed to update record for {}: {}", package, why)]
     RecordUpdate { package: String, why: io::Error },
     #[fail(display = "rsyncing {:?} to {:?} failed: {}", src, dst, why)]
     Rsync { src: PathBuf, dst: PathBuf, why: io::Error },
+    #[fail(display = "'{}' uses an sbuild escape which the {} backend does not support", command, backend)]
+    UnsupportedEscape { command: String, backend: &'static str },
 }
 
 impl From<LinkError> for BuildError {
@@ -318,7 +371,7 @@ pub fn build(config: &Config, item: &Source, pwd: &Path, suite: &str, component:
                 unimplemented!()
             }
             Some(DebianPath::Branch { ref url, ref branch }) => {
-                merge_branch(url, branch)
+                merge_branch(url, branch, &project_directory)
                     .map_err(|why| BuildError::GitBranch {
                         package: item.name.clone(),
                         branch: branch.clone(),
@@ -381,8 +434,6 @@ pub fn build(config: &Config, item: &Source, pwd: &Path, suite: &str, component:
         }
     }
 
-    let _ = env::set_current_dir(&["build/", suite].concat());
-
     let skipped = pre_flight(
         config,
         item,
@@ -395,24 +446,28 @@ pub fn build(config: &Config, item: &Source, pwd: &Path, suite: &str, component:
     )?;
 
     if !skipped && dsc_file.is_some() {
-        misc::copy_here(&item.name).map_err(|why| {
+        misc::copy_into(&project_directory, &pwd.join(["build/", suite].concat())).map_err(|why| {
             BuildError::DscMove { why }
         })?;
     }
 
-    let _ = env::set_current_dir("../..");
     Ok(())
 }
 
-fn merge_branch(url: &str, branch: &str) -> io::Result<()> {
-    fs::create_dir_all("/tmp/debrep")?;
-    fs::remove_dir_all("/tmp/debrep/repo")?;
+/// Copies the debian directory of a branch into the project, via a clone of the branch which
+/// is private to the source, as sources may be built concurrently.
+fn merge_branch(url: &str, branch: &str, project_directory: &Path) -> io::Result<()> {
+    let temp = tempfile::tempdir()?;
+    let clone = temp.path().join("repo");
     Command::new("git")
-        .args(&["clone", "-b", branch, url, "/tmp/debrep/repo"])
+        .args(&["clone", "-b", branch, url])
+        .arg(&clone)
         .run()?;
 
     Command::new("cp")
-        .args(&["-r", "/tmp/debrep/repo/debian", "."])
+        .arg("-r")
+        .arg(clone.join("debian"))
+        .arg(project_directory)
         .run()
 }
 
@@ -427,7 +482,7 @@ fn pre_flight(
     force: bool
 ) -> Result<bool, BuildError> {
     let name = &item.name;
-    let record_path = PathBuf::from(["../../record/", suite, "/", &name].concat());
+    let record_path = pwd.join(["record/", suite, "/", &name].concat());
 
     enum Record<'a> {
         Dsc(&'a str),
@@ -545,7 +600,7 @@ fn pre_flight(
     config
         .architectures
         .iter()
-        .try_for_each(|arch| sbuild(config, item, &pwd, suite, component, dir, arch))?;
+        .try_for_each(|arch| build_arch(config, item, &pwd, suite, component, dir, arch))?;
 
     let result = match record {
         Some(Record::Dsc(dsc)) => {
@@ -570,121 +625,113 @@ fn pre_flight(
     Ok(false)
 }
 
-fn sbuild<P: AsRef<Path>>(
+fn build_arch(
     config: &Config,
     item: &Source,
     pwd: &Path,
     suite: &str,
     component: &str,
-    path: P,
+    path: &Path,
     arch: &str,
 ) -> Result<(), BuildError> {
-    let log_path = pwd.join(["logs/", suite, "/", &format!("{}-{}", item.name, arch)].concat());
-    let mut command = Exec::cmd("sbuild")
-        .args(&[
-            "-v", "--log-external-command-output", "--log-external-command-error",
-            &format!("--host={}", arch),
-            // "--dpkg-source-opt=-Zgzip", // Use this when testing
-            "-d", suite
-        ])
-        .stdout(Redirection::Merge)
-        .stderr(Redirection::File(
-            fs::OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(&log_path)
-                .map_err(|why| BuildError::Open { file: log_path, why })?
-        ));
-
-    if let Some(ref depends) = item.depends {
-        let pool = pwd.join(&["repo/pool/", suite, "/", component].concat());
-        let deb_iter = misc::walk_debs(&pool, false)
-            .flat_map(|deb| misc::match_deb(&deb, depends));
-
-        let mut temp: Vec<(String, usize, String, String)> = Vec::new();
-        for (deb, pos) in deb_iter {
-            let (name, version) = debian::get_debian_package_info(&Path::new(&deb))
-                .expect("failed to get debian name & version");
-
-            let mut found = false;
-            for stored_dep in &mut temp {
-                if stored_dep.2 == name {
-                    found = true;
-                    if deb_version::compare_versions(&stored_dep.3, &version) == Ordering::Less {
-                        stored_dep.0 = deb.clone();
-                        stored_dep.1 = pos;
-                        stored_dep.2 = name.clone();
-                        stored_dep.3 = version.clone();
-                        continue
-                    }
-                }
-            }
+    let output = job_output(pwd, suite, &item.name, arch);
+    if output.exists() {
+        fs::remove_dir_all(&output)
+            .map_err(|why| BuildError::Directory { path: output.clone(), why })?;
+    }
 
-            if ! found {
-                temp.push((deb, pos, name, version));
-            }
-        }
+    fs::create_dir_all(&output)
+        .map_err(|why| BuildError::Directory { path: output.clone(), why })?;
 
-        if depends.len() != temp.len() {
-            for dependency in depends {
-                if !temp.iter().any(|x| x.0.contains(dependency)) {
-                    error!("dependency for {} not found: {}", path.as_ref().display(), dependency)
-                }
-            }
+    // Packages are written to the parent of a source directory, so the source is built from a
+    // copy within the output directory.
+    let copy;
+    let path = if path.extension().map_or(false, |ext| ext == "dsc") {
+        path
+    } else {
+        rsync(path, &output)
+            .map_err(|why| BuildError::Rsync { src: path.to_path_buf(), dst: output.clone(), why })?;
+        copy = output.join(path.file_name().unwrap());
+        &copy
+    };
 
-            return Err(BuildError::MissingDependencies);
-        }
+    let job = BuildJob {
+        package: &item.name,
+        suite,
+        arch,
+        path,
+        output,
+        log: pwd.join(["logs/", suite, "/", &format!("{}-{}", item.name, arch)].concat()),
+        extra_packages: extra_packages(item, pwd, suite, component, path)?,
+        extra_repositories: config.extra_repos.as_ref().map_or(&[], |repos| repos.as_slice()),
+        extra_keys: &config.extra_keys,
+        prebuild: item.prebuild.as_ref().map_or(&[], |commands| commands.as_slice()),
+        starting_build: item.starting_build.as_ref().map_or(&[], |commands| commands.as_slice()),
+    };
 
-        temp.sort_by(|a, b| a.1.cmp(&b.1));
-        for &(ref p, _, _, _) in &temp {
-            command = command.arg(&["--extra-package=", &p].concat());
-        }
-    }
+    backend::from_config(&item.builder, pwd).build(&job)
+}
 
-    for key in &config.extra_keys {
-        command = command.arg(&format!("--extra-repository-key={}", key.display()));
-    }
+/// The directory which the packages of a source are written to when it is built for an
+/// architecture, so that concurrent builds do not share their output.
+fn job_output(pwd: &Path, suite: &str, source: &str, arch: &str) -> PathBuf {
+    pwd.join(["build/", suite, "/.out/", source, "_", arch].concat())
+}
 
-    if let Some(repos) = config.extra_repos.as_ref() {
-        for repo in repos {
-            command = command.arg(&["--extra-repository=", &repo].concat());
-        }
-    }
+/// The newest archive within the pool of each of the source's dependencies, in the order that
+/// the dependencies are listed.
+fn extra_packages(
+    item: &Source,
+    pwd: &Path,
+    suite: &str,
+    component: &str,
+    path: &Path,
+) -> Result<Vec<PathBuf>, BuildError> {
+    let depends = match item.depends {
+        Some(ref depends) => depends,
+        None => return Ok(Vec::new())
+    };
 
-    if let Some(commands) = item.prebuild.as_ref() {
-        for cmd in commands {
-            command = command.arg(&["--pre-build-commands=", &cmd].concat());
+    let pool = pwd.join(&["repo/pool/", suite, "/", component].concat());
+    let deb_iter = misc::walk_debs(&pool, 0)
+        .flat_map(|deb| misc::match_deb(&deb, depends));
+
+    let mut temp: Vec<(String, usize, String, String)> = Vec::new();
+    for (deb, pos) in deb_iter {
+        let (name, version) = debian::get_debian_package_info(&Path::new(&deb))
+            .expect("failed to get debian name & version");
+
+        let mut found = false;
+        for stored_dep in &mut temp {
+            if stored_dep.2 == name {
+                found = true;
+                if deb_version::compare_versions(&stored_dep.3, &version) == Ordering::Less {
+                    stored_dep.0 = deb.clone();
+                    stored_dep.1 = pos;
+                    stored_dep.2 = name.clone();
+                    stored_dep.3 = version.clone();
+                    continue
+                }
+            }
         }
-    }
 
-    if let Some(commands) = item.starting_build.as_ref() {
-        for cmd in commands {
-            command = command.arg(&["--starting-build-commands=", &cmd].concat());
+        if ! found {
+            temp.push((deb, pos, name, version));
         }
     }
 
-    command = command.arg(path.as_ref());
-
-    debug!("executing {:#?}", command);
-
-    let exit_status = command.join()
-        .map_err(|why| BuildError::Command {
-            cmd: "sbuild",
-            why: io::Error::new(
-                io::ErrorKind::Other,
-                format!("{:?}", why)
-            )
-        })?;
+    if depends.len() != temp.len() {
+        for dependency in depends {
+            if !temp.iter().any(|x| x.0.contains(dependency)) {
+                error!("dependency for {} not found: {}", path.display(), dependency)
+            }
+        }
 
-    if exit_status.success() {
-        Ok(())
-    } else {
-        Err(BuildError::Build {
-            package: item.name.clone(),
-            reason: exit_status
-        })
+        return Err(BuildError::MissingDependencies);
     }
+
+    temp.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(temp.into_iter().map(|(deb, _, _, _)| PathBuf::from(deb)).collect())
 }
 
 fn debchange_git(suite: &str, version: &str, project_directory: &Path, branch: &Option<String>, commit: &Option<String>) -> io::Result<()> {
@@ -0,0 +1,236 @@
+use config::Source;
+use rayon::{self, Scope};
+use std::sync::Mutex;
+use super::BuildError;
+
+/// The sources to build, ordered by the dependencies between them.
+///
+/// A source depends upon another source when its `depends` lists the name of that source, or
+/// the name of any binary package which that source builds.
+pub struct BuildGraph<'a> {
+    sources: Vec<&'a Source>,
+    /// The sources which depend upon each source.
+    dependents: Vec<Vec<usize>>,
+    /// The number of sources that each source depends upon.
+    dependencies: Vec<usize>,
+}
+
+struct State {
+    /// The number of dependencies of each source which have yet to be built.
+    remaining: Vec<usize>,
+    skipped: Vec<bool>,
+    failures: Vec<(String, BuildError)>,
+}
+
+impl<'a> BuildGraph<'a> {
+    /// The binaries of each source are given in the same order as the sources.
+    pub fn new(sources: Vec<&'a Source>, binaries: &[Vec<String>]) -> Result<Self, BuildError> {
+        let mut dependents = vec![Vec::new(); sources.len()];
+        let mut dependencies = vec![0; sources.len()];
+
+        for (id, source) in sources.iter().enumerate() {
+            let depends = match source.depends {
+                Some(ref depends) => depends,
+                None => continue
+            };
+
+            for (dependency, other) in sources.iter().enumerate() {
+                let builds = |name: &String| *name == other.name || binaries[dependency].contains(name);
+                if dependency != id && depends.iter().any(builds) {
+                    dependents[dependency].push(id);
+                    dependencies[id] += 1;
+                }
+            }
+        }
+
+        let graph = BuildGraph { sources, dependents, dependencies };
+        graph.check_for_cycles()?;
+        Ok(graph)
+    }
+
+    /// Sources which are never freed of their dependencies are within a cycle, or depend upon
+    /// a source which is.
+    fn check_for_cycles(&self) -> Result<(), BuildError> {
+        let mut remaining = self.dependencies.clone();
+        let mut ready = (0..self.sources.len()).filter(|&id| remaining[id] == 0).collect::<Vec<_>>();
+
+        while let Some(id) = ready.pop() {
+            for &dependent in &self.dependents[id] {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+
+        let cycle = (0..self.sources.len())
+            .filter(|&id| remaining[id] != 0)
+            .map(|id| self.sources[id].name.as_str())
+            .collect::<Vec<&str>>();
+
+        if cycle.is_empty() {
+            Ok(())
+        } else {
+            Err(BuildError::DependencyCycle { packages: cycle.join(", ") })
+        }
+    }
+
+    /// Builds each source as soon as the sources that it depends upon have been built, with
+    /// up to `jobs` sources being built at a time.
+    ///
+    /// Sources which depend upon a source that failed to build are skipped. The name of each
+    /// source that failed or was skipped is returned with the reason why.
+    pub fn build<F>(&self, jobs: usize, build: F) -> Vec<(String, BuildError)>
+        where F: Fn(&Source) -> Result<(), BuildError> + Sync
+    {
+        let state = Mutex::new(State {
+            remaining: self.dependencies.clone(),
+            skipped: vec![false; self.sources.len()],
+            failures: Vec::new(),
+        });
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.max(1))
+            .build()
+            .expect("failed to create build thread pool");
+
+        pool.scope(|scope| {
+            for id in (0..self.sources.len()).filter(|&id| self.dependencies[id] == 0) {
+                self.spawn(scope, id, &state, &build);
+            }
+        });
+
+        state.into_inner().unwrap().failures
+    }
+
+    fn spawn<'s, F>(&'s self, scope: &Scope<'s>, id: usize, state: &'s Mutex<State>, build: &'s F)
+        where F: Fn(&Source) -> Result<(), BuildError> + Sync
+    {
+        scope.spawn(move |scope| {
+            let source = self.sources[id];
+            match build(source) {
+                Ok(()) => {
+                    let ready = {
+                        let mut state = state.lock().unwrap();
+                        self.dependents[id].iter()
+                            .cloned()
+                            .filter(|&dependent| {
+                                state.remaining[dependent] -= 1;
+                                state.remaining[dependent] == 0 && !state.skipped[dependent]
+                            })
+                            .collect::<Vec<usize>>()
+                    };
+
+                    for dependent in ready {
+                        self.spawn(scope, dependent, state, build);
+                    }
+                }
+                Err(why) => {
+                    let mut state = state.lock().unwrap();
+                    state.failures.push((source.name.clone(), why));
+                    self.skip_dependents(id, &mut state);
+                }
+            }
+        });
+    }
+
+    fn skip_dependents(&self, id: usize, state: &mut State) {
+        for &dependent in &self.dependents[id] {
+            if !state.skipped[dependent] {
+                state.skipped[dependent] = true;
+                state.failures.push((
+                    self.sources[dependent].name.clone(),
+                    BuildError::DependencyFailed { dependency: self.sources[id].name.clone() }
+                ));
+                self.skip_dependents(dependent, state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph<'a>(sources: &'a [Source], binaries: &[&[&str]]) -> Result<BuildGraph<'a>, BuildError> {
+        let binaries = (0..sources.len())
+            .map(|id| binaries.get(id).map_or(Vec::new(), |names| names.iter().map(|&name| name.to_owned()).collect()))
+            .collect::<Vec<Vec<String>>>();
+
+        BuildGraph::new(sources.iter().collect(), &binaries)
+    }
+
+    fn source(name: &str, depends: &[&str]) -> Source {
+        Source {
+            name: name.to_owned(),
+            version: None,
+            location: None,
+            assets: None,
+            starting_build: None,
+            prebuild: None,
+            build_on: None,
+            repos: None,
+            keep_source: true,
+            debian: None,
+            depends: Some(depends.iter().map(|&depend| depend.to_owned()).collect()),
+            retain: 3,
+            extract: true,
+            publish_versions: None,
+            builder: Default::default(),
+        }
+    }
+
+    #[test]
+    fn build_order() {
+        let sources = vec![source("app", &["libfoo", "libc6"]), source("libfoo", &["libbar"]), source("libbar", &[])];
+        let graph = graph(&sources, &[]).unwrap();
+
+        let built = Mutex::new(Vec::new());
+        let failures = graph.build(2, |source| {
+            built.lock().unwrap().push(source.name.clone());
+            Ok(())
+        });
+
+        assert!(failures.is_empty());
+        assert_eq!(built.into_inner().unwrap(), vec!["libbar", "libfoo", "app"]);
+    }
+
+    #[test]
+    fn failed_dependencies() {
+        let sources = vec![source("app", &["libfoo"]), source("libfoo", &[]), source("other", &[])];
+        let graph = graph(&sources, &[]).unwrap();
+
+        let mut failures = graph.build(2, |source| if source.name == "libfoo" {
+            Err(BuildError::MissingDependencies)
+        } else {
+            Ok(())
+        }).into_iter().map(|(name, _)| name).collect::<Vec<String>>();
+
+        failures.sort();
+        assert_eq!(failures, vec!["app", "libfoo"]);
+    }
+
+    #[test]
+    fn cycles() {
+        let sources = vec![source("a", &["b"]), source("b", &["c"]), source("c", &["a"]), source("d", &[])];
+        match graph(&sources, &[]) {
+            Err(BuildError::DependencyCycle { packages }) => assert_eq!(packages, "a, b, c"),
+            _ => panic!("cycle was not detected")
+        }
+    }
+
+    #[test]
+    fn binary_dependencies() {
+        let sources = vec![source("app", &["libfoo-dev"]), source("libfoo", &[])];
+        let graph = graph(&sources, &[&["app"], &["libfoo1", "libfoo-dev"]]).unwrap();
+
+        let built = Mutex::new(Vec::new());
+        let failures = graph.build(2, |source| {
+            built.lock().unwrap().push(source.name.clone());
+            Ok(())
+        });
+
+        assert!(failures.is_empty());
+        assert_eq!(built.into_inner().unwrap(), vec!["libfoo", "app"]);
+    }
+}
@@ -0,0 +1,185 @@
+use bincode;
+use debian::{AppStreamEntry, ContentsEntry, PackageEntry};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Incremented whenever the layout of the cache changes, so that older caches are discarded.
+const CACHE_VERSION: u32 = 3;
+
+/// Identifies a specific revision of an archive in the pool. If any of these values differ from
+/// the cached values, the archive will be processed again.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CacheKey {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+}
+
+impl CacheKey {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let metadata = path.metadata()?;
+        Ok(CacheKey {
+            size: metadata.len(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct CachedArchive {
+    key: CacheKey,
+    package: PackageEntry,
+    contents: ContentsEntry,
+    appstream: Option<AppStreamEntry>,
+}
+
+/// The package and contents entries of every archive that was processed by the previous run.
+#[derive(Default, Deserialize, Serialize)]
+pub struct DistCache {
+    version: u32,
+    archives: HashMap<PathBuf, CachedArchive>,
+}
+
+impl DistCache {
+    /// Loads the cache at the given path. A missing, outdated, or corrupted cache is discarded.
+    pub fn load(path: &Path) -> DistCache {
+        if !path.exists() {
+            return DistCache::default();
+        }
+
+        let result = File::open(path)
+            .map_err(|why| why.to_string())
+            .and_then(|file| {
+                bincode::deserialize_from::<_, DistCache>(BufReader::new(file))
+                    .map_err(|why| why.to_string())
+            });
+
+        match result {
+            Ok(cache) => if cache.version == CACHE_VERSION {
+                info!("loaded {} cached archives from {}", cache.archives.len(), path.display());
+                cache
+            } else {
+                warn!("discarding outdated dist cache at {}", path.display());
+                DistCache::default()
+            },
+            Err(why) => {
+                warn!("discarding invalid dist cache at {}: {}", path.display(), why);
+                DistCache::default()
+            }
+        }
+    }
+
+    /// Fetches the entries of an archive, if the archive has not changed since it was cached.
+    pub fn get(&self, path: &Path, key: CacheKey) -> Option<(PackageEntry, ContentsEntry, Option<AppStreamEntry>)> {
+        self.archives.get(path)
+            .filter(|cached| cached.key == key)
+            .map(|cached| (cached.package.clone(), cached.contents.clone(), cached.appstream.clone()))
+    }
+
+    pub fn insert(
+        &mut self,
+        path: PathBuf,
+        key: CacheKey,
+        package: PackageEntry,
+        contents: ContentsEntry,
+        appstream: Option<AppStreamEntry>
+    ) {
+        self.archives.insert(path, CachedArchive { key, package, contents, appstream });
+    }
+
+    /// Moves the entries of archives within one directory to another, such as when the archives
+    /// of a pool are hard-linked elsewhere. Their keys are unchanged by hard links.
+    pub fn relocate(self, from: &Path, to: &Path) -> DistCache {
+        let archives = self.archives.into_iter()
+            .filter_map(|(path, mut cached)| {
+                let path = to.join(path.strip_prefix(from).ok()?);
+                cached.package.filename = path.clone();
+                Some((path, cached))
+            })
+            .collect();
+
+        DistCache { version: self.version, archives }
+    }
+
+    /// Writes the cache to a temporary file, which then replaces the cache at the given path.
+    ///
+    /// An interrupted write will therefore never leave a partial cache behind.
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        self.version = CACHE_VERSION;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = path.with_extension("partial");
+        {
+            let mut file = BufWriter::new(File::create(&temporary)?);
+            bincode::serialize_into(&mut file, self)
+                .map_err(|why| io::Error::new(io::ErrorKind::Other, format!("{}", why)))?;
+        }
+
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Removes the cache, so that every archive in the pool will be processed again.
+pub fn clear(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        info!("removing dist cache at {}", path.display());
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile;
+
+    fn entries(path: &Path) -> (PackageEntry, ContentsEntry) {
+        let package = PackageEntry {
+            control: BTreeMap::new(),
+            filename: path.to_path_buf(),
+            size: 1,
+            md5sum: "md5".into(),
+            sha1: "sha1".into(),
+            sha256: "sha256".into(),
+            sha512: "sha512".into(),
+        };
+
+        let contents = ContentsEntry {
+            component: "main".into(),
+            package: "misc/package".into(),
+            files: vec![PathBuf::from("./usr/bin/package")]
+        };
+        (package, contents)
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("dists/bionic.cache");
+        let archive = PathBuf::from("pool/bionic/main/binary-amd64/p/package/package_1.0_amd64.deb");
+        let key = CacheKey { size: 1, mtime: 2, mtime_nsec: 3, inode: 4 };
+
+        let mut cache = DistCache::default();
+        let (package, contents) = entries(&archive);
+        cache.insert(archive.clone(), key, package, contents, None);
+        cache.save(&cache_path).unwrap();
+
+        let cache = DistCache::load(&cache_path);
+        assert!(cache.get(&archive, key).is_some());
+        assert!(cache.get(&archive, CacheKey { mtime: 5, ..key }).is_none());
+
+        clear(&cache_path).unwrap();
+        assert!(DistCache::load(&cache_path).get(&archive, key).is_none());
+    }
+}
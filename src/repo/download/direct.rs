@@ -12,12 +12,18 @@ pub enum DownloadResult {
 }
 
 /// Given an item with a URL, download the item if the item does not already exist.
-pub fn download(client: Arc<Client>, item: &Direct, suite: &str, component: &str) -> io::Result<DownloadResult> {
+pub fn download(
+    client: Arc<Client>,
+    item: &Direct,
+    suite: &str,
+    component: &str,
+    architectures: &[String]
+) -> io::Result<DownloadResult> {
     info!("checking if {} needs to be downloaded", item.name);
 
     let mut downloaded = 0;
 
-    for (destination, path) in item.get_destinations(suite, component)?.into_iter().zip(item.urls.iter()) {
+    for (destination, path) in item.get_destinations(suite, component, architectures)?.into_iter().zip(item.urls.iter()) {
         let checksum = path.checksum.as_ref().map(|x| x.as_str());
         // If the file is to be repackaged, store it in the assets directory, else the pool.
         let target = destination.assets.as_ref().map_or(&destination.pool, |x| &x.1);
@@ -29,7 +35,12 @@ pub fn download(client: Arc<Client>, item: &Direct, suite: &str, component: &str
 }
 
 /// Downloads pre-built Debian packages in parallel
-pub fn parallel(items: &[Direct], suite: &str, component: &str) -> Vec<io::Result<DownloadResult>> {
+pub fn parallel(
+    items: &[Direct],
+    suite: &str,
+    component: &str,
+    architectures: &[String]
+) -> Vec<io::Result<DownloadResult>> {
     let client = Arc::new(Client::new());
 
     // Only up to 8 downloads at a time.
@@ -40,7 +51,7 @@ pub fn parallel(items: &[Direct], suite: &str, component: &str) -> Vec<io::Resul
 
     thread_pool.install(|| {
         items.par_iter()
-            .map(|item| download(client.clone(), item, suite, component))
+            .map(|item| download(client.clone(), item, suite, component, architectures))
             .collect()
     })
 }
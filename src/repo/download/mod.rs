@@ -15,7 +15,8 @@ pub fn all(config: &Config) {
     let mut errors = Vec::new();
 
     if let Some(ref ddl_sources) = config.direct {
-        for (id, result) in direct::parallel(ddl_sources, &config.archive, &config.default_component)
+        let architectures = config.all_architectures();
+        for (id, result) in direct::parallel(ddl_sources, &config.archive, &config.default_component, &architectures)
             .into_iter()
             .enumerate()
         {
@@ -80,7 +81,16 @@ pub fn packages(sources: &Config, packages: &[&str]) {
 
     if let Some(ref source) = sources.direct.as_ref() {
         for source in source.iter().filter(|s| packages.contains(&s.name.as_str())) {
-            if let Err(why) = direct::download(client.clone(), source, &sources.archive, &sources.default_component) {
+            let architectures = sources.all_architectures();
+            let result = direct::download(
+                client.clone(),
+                source,
+                &sources.archive,
+                &sources.default_component,
+                &architectures
+            );
+
+            if let Err(why) = result {
                 error!("failed to download {}: {}", &source.name, why);
                 exit(1);
             }
@@ -1,241 +1,449 @@
-use checksum::hasher;
-use config::Config;
+use checksum::{checksums, hasher};
+use chrono::{Duration, Utc};
+use compress::*;
+use config::{ArchAll, Config};
 use debian::{self, *};
 use debarchive::Archive as DebArchive;
-use md5::Md5;
 use misc;
 use rayon::{self, prelude::*};
-use sha1::Sha1;
-use sha2::{Sha256, Sha512};
-use std::cmp::Ordering;
+use sha2::Sha256;
+use super::cache::{CacheKey, DistCache};
 use std::collections::hash_map::{HashMap, Entry};
-use std::{env, fs::{self, File}, io::{self, Write}, path::{Path, PathBuf}, process::{Command, Stdio}};
+use std::{ffi::OsStr, fs::{self, File}, io, path::{Path, PathBuf}, process::Command};
 use deb_version::compare_versions;
+use walkdir::WalkDir;
 
-use compress::*;
 
-pub(crate) fn sources_index(component: &str, dist_base: &str, pool_base: &str) -> io::Result<()> {
-    let pool_path = PathBuf::from(pool_base).join("source");
-    if ! pool_path.exists() {
-        return Ok(());
-    }
+/// The `Date` of a release signed now, and its `Valid-Until` date if the release should expire.
+pub(crate) fn release_dates(config: &Config) -> (String, Option<String>) {
+    const FORMAT: &str = "%a, %d %b %Y %H:%M:%S UTC";
+    let now = Utc::now();
+    let valid_until = config.valid_for
+        .map(|days| (now + Duration::days(i64::from(days))).format(FORMAT).to_string());
 
-    info!("generating sources index");
-    let path = PathBuf::from([dist_base, "/", component, "/source/"].concat());
-    fs::create_dir_all(&path)?;
-
-    Command::new("apt-ftparchive")
-        .arg("sources")
-        .arg(pool_path)
-        .stderr(Stdio::inherit())
-        .stdout(Stdio::piped())
-        .spawn()
-        .and_then(|mut child| {
-            {
-                let stdout = child.stdout.as_mut().unwrap();
-                compress("Sources", &path, stdout, UNCOMPRESSED | GZ_COMPRESS | XZ_COMPRESS)?;
-            }
-
-            child.wait().and_then(|stat| {
-                if stat.success() {
-                    Ok(())
-                } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "apt-ftparchive failed"))
-                }
-            })
-        })
+    (now.format(FORMAT).to_string(), valid_until)
 }
 
-// TODO: this can easily be replaced with Rust.
-/// Generates the dists release file via `apt-ftparchive`.
+/// Generates the dists release file from the indexes that were written to the dist directory.
 pub(crate) fn dists_release(config: &Config, base: &str, components: &[String]) -> io::Result<()> {
     info!("generating dists release files");
 
-    let cwd = env::current_dir()?;
-    env::set_current_dir(base)?;
+    let base = Path::new(base);
+    let (date, valid_until) = release_dates(config);
+    let release = Release {
+        origin: config.origin.clone(),
+        label: config.label.clone(),
+        suite: config.suite_name().to_owned(),
+        version: config.version.clone(),
+        codename: config.archive.clone(),
+        date,
+        valid_until,
+        not_automatic: config.not_automatic,
+        but_automatic_upgrades: config.but_automatic_upgrades,
+        acquire_by_hash: config.by_hash.is_some(),
+        no_support_for_architecture_all: config.arch_all == ArchAll::Both,
+        architectures: {
+            let mut architectures = config.all_architectures();
+            if config.arch_all.separate() {
+                architectures.push("all".into());
+            }
+            architectures
+        },
+        components: components.to_vec(),
+        description: format!("{} ({} {})", config.label, config.archive, config.version),
+        entries: debian::collect_entries(base)?,
+    };
+
+    release.write(File::create(base.join("Release"))?)
+}
 
-    let components = components.iter()
-        .fold(String::new(), |mut acc, x| {
-            acc.push_str(&x);
-            acc.push(' ');
-            acc
-        });
+/// Links each index in the dist directory into the `by-hash/SHA256/` directory beside it, and
+/// removes the generations which exceed the number that should be kept.
+pub(crate) fn by_hash(base: &str, generations: usize) -> io::Result<()> {
+    info!("linking indexes into by-hash directories");
+
+    let indexes = WalkDir::new(base)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != OsStr::new(BY_HASH))
+        .flat_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_hashed_index(e.file_name()))
+        .map(|e| e.path().to_path_buf())
+        .collect::<Vec<PathBuf>>();
+
+    let digests = indexes.into_par_iter()
+        .map(|index| File::open(&index).and_then(hasher::<Sha256, File>).map(|digest| (index, digest)))
+        .collect::<io::Result<Vec<(PathBuf, String)>>>()?;
+
+    let mut current: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for (index, digest) in digests {
+        let directory = index.parent().unwrap().join(BY_HASH).join("SHA256");
+        fs::create_dir_all(&directory)?;
+
+        let target = directory.join(&digest);
+        if target.exists() {
+            fs::remove_file(&target)?;
+        }
 
-    let release = Command::new("apt-ftparchive")
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Origin={}",
-            config.origin
-        ))
-        .arg("-o")
-        .arg(format!("APT::FTPArchive::Release::Label={}", config.label))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Suite={}",
-            config.archive
-        ))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Version={}",
-            config.version
-        ))
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Codename={}",
-            config.archive
-        ))
-        .arg("-o")
-        .arg("APT::FTPArchive::Release::Architectures=i386 amd64 all")
-        .arg("-o")
-        .arg(["APT::FTPArchive::Release::Components=", components.trim_right()].concat())
-        .arg("-o")
-        .arg(format!(
-            "APT::FTPArchive::Release::Description={} ({} {})",
-            config.label, config.archive, config.version
-        ))
-        .arg("release")
-        .arg(".")
-        .output()
-        .map(|data| data.stdout)?;
-
-    let mut release_file = File::create("Release")?;
-    release_file.write_all(&release)?;
-    env::set_current_dir(cwd)
+        fs::hard_link(&index, &target)?;
+        current.entry(directory).or_insert_with(Vec::new).push(target);
+    }
+
+    for (directory, current) in current {
+        prune_by_hash(&directory, &current, generations)?;
+    }
+
+    Ok(())
 }
 
-/// Generates the `InRelease` file from the `Release` file via `gpg --clearsign`.
-pub(crate) fn gpg_in_release(email: &str, release_path: &Path, out_path: &Path) -> io::Result<()> {
-    info!("generating InRelease file");
-    let exit_status = Command::new("gpg")
-        .args(&[
-            "--clearsign",
-            "--local-user",
-            email,
-            "--batch",
-            "--yes",
-            "--digest-algo",
-            "sha512",
-            "-o",
-        ])
-        .arg(out_path)
-        .arg(release_path)
-        .status()?;
-
-    if exit_status.success() {
-        Ok(())
-    } else {
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            "gpg_in_release failed",
-        ))
+/// Links the by-hash directories of the published dists into the dists that will replace them.
+pub(crate) fn link_by_hash(dists: &Path, base: &Path) -> io::Result<()> {
+    let files = WalkDir::new(dists)
+        .into_iter()
+        .flat_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.path().components().any(|c| c.as_os_str() == OsStr::new(BY_HASH)));
+
+    for file in files {
+        let target = base.join(file.path().strip_prefix(dists).unwrap());
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::hard_link(file.path(), &target)?;
     }
+
+    Ok(())
+}
+
+fn is_hashed_index(name: &OsStr) -> bool {
+    name.to_str().map_or(false, |name| {
+        name.starts_with("Packages") || name.starts_with("Sources") || name.starts_with("Contents")
+            || name.starts_with("Translation") || name.starts_with("Components")
+            || name.starts_with("icons-") || name == "Index"
+    })
 }
 
-/// Generates the `Release.gpg` file from the `Release` file via `gpg -abs`
-pub(crate) fn gpg_release(email: &str, release_path: &Path, out_path: &Path) -> io::Result<()> {
-    info!("generating Release.gpg file");
-    let exit_status = Command::new("gpg")
-        .args(&[
-            "-abs",
-            "--local-user",
-            email,
-            "--batch",
-            "--yes",
-            "--digest-algo",
-            "sha512",
-            "-o",
-        ])
-        .arg(out_path)
-        .arg(release_path)
-        .status()?;
-
-    if exit_status.success() {
-        Ok(())
+/// Generates ed-style patches between the published `Packages` and `Sources` indexes and the
+/// indexes in the dist directory, so that APT may fetch only what changed between generations.
+pub(crate) fn pdiffs(dists: &Path, base: &str, history: usize) -> io::Result<()> {
+    info!("generating pdiffs for indexes");
+
+    let base = Path::new(base);
+    let indexes = WalkDir::new(base)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != OsStr::new(BY_HASH))
+        .flat_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| e.file_name() == OsStr::new("Packages") || e.file_name() == OsStr::new("Sources"))
+        .map(|e| e.path().strip_prefix(base).unwrap().to_path_buf())
+        .collect::<Vec<PathBuf>>();
+
+    // Patches are named after the time at which they were generated, as dak does.
+    let name = Utc::now().format("%Y-%m-%d-%H%M.%S").to_string();
+
+    indexes.into_par_iter()
+        .map(|index| pdiff(&dists.join(&index), &base.join(&index), &name, history))
+        .collect()
+}
+
+fn pdiff(previous: &Path, current: &Path, name: &str, history: usize) -> io::Result<()> {
+    // Without a published index, there is nothing to generate a patch against.
+    if !previous.exists() {
+        return Ok(());
+    }
+
+    let diff_dir = [current.file_name().unwrap().to_str().unwrap(), PDIFF_SUFFIX].concat();
+    let previous_diffs = previous.with_file_name(&diff_dir);
+    let current_diffs = current.with_file_name(&diff_dir);
+    fs::create_dir_all(&current_diffs)?;
+
+    let previous_index = previous_diffs.join("Index");
+    let mut index = if previous_index.exists() {
+        PDiffIndex::parse(&misc::read_to_string(&previous_index)?)?
     } else {
-        Err(io::Error::new(io::ErrorKind::Other, "gpg_release failed"))
+        PDiffIndex::default()
+    };
+
+    let previous_sha256 = File::open(previous).and_then(hasher::<Sha256, File>)?;
+    let current_sha256 = File::open(current).and_then(hasher::<Sha256, File>)?;
+
+    if previous_sha256 != current_sha256 {
+        info!("generating pdiff for {}", current.display());
+        let output = Command::new("diff").arg("--ed").arg(previous).arg(current).output()?;
+
+        // `diff` exits with a status of 1 when the files differ, and 2 when it fails.
+        if output.status.code() != Some(1) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("diff of {} and {} failed: {}", previous.display(), current.display(), output.status)
+            ));
+        }
+
+        let patch = output.stdout;
+        compress(name, &current_diffs, patch.as_slice(), &[Format::new(Codec::Gz)])?;
+        let download = [name, ".gz"].concat();
+        let download_path = current_diffs.join(&download);
+
+        index.patches.push(PDiffPatch {
+            history: PDiffFile {
+                sha256: previous_sha256,
+                size: previous.metadata()?.len(),
+                name: name.to_owned()
+            },
+            patch: PDiffFile {
+                sha256: hasher::<Sha256, &[u8]>(patch.as_slice())?,
+                size: patch.len() as u64,
+                name: name.to_owned()
+            },
+            download: PDiffFile {
+                sha256: File::open(&download_path).and_then(hasher::<Sha256, File>)?,
+                size: download_path.metadata()?.len(),
+                name: download
+            },
+        });
+    }
+
+    for expired in index.expire(history) {
+        debug!("expiring pdiff {} of {}", expired.download.name, current.display());
+    }
+
+    // Patches of previous generations are carried over from the published dists. A patch which
+    // can not be carried over is dropped, along with the older patches, as clients must apply
+    // every patch after the generation that they have.
+    let mut usable = 0;
+    for (position, patch) in index.patches.iter().enumerate() {
+        let target = current_diffs.join(&patch.download.name);
+        if target.exists() {
+            continue
+        }
+
+        if let Err(why) = fs::hard_link(previous_diffs.join(&patch.download.name), &target) {
+            warn!("dropping pdiff {} of {}: {}", patch.download.name, current.display(), why);
+            usable = position + 1;
+        }
+    }
+
+    for dropped in index.patches.drain(..usable) {
+        debug!("dropped pdiff {} of {}", dropped.download.name, current.display());
+    }
+
+    index.current_sha256 = current_sha256;
+    index.current_size = current.metadata()?.len();
+    index.write(File::create(current_diffs.join("Index"))?)
+}
+
+/// Keeps the current indexes, and the most recent of the previous generations.
+fn prune_by_hash(directory: &Path, current: &[PathBuf], generations: usize) -> io::Result<()> {
+    let mut previous = Vec::new();
+    for entry in directory.read_dir()? {
+        let entry = entry?;
+        let path = entry.path();
+        if !current.contains(&path) {
+            previous.push((entry.metadata()?.modified()?, path));
+        }
     }
+
+    previous.sort_by(|a, b| b.0.cmp(&a.0));
+    let keep = generations.saturating_sub(1) * current.len();
+    for (_, path) in previous.into_iter().skip(keep) {
+        info!("removing expired index at {}", path.display());
+        fs::remove_file(&path)?;
+    }
+
+    Ok(())
 }
 
-fn binary_suites(pool_base: &Path) -> io::Result<Vec<(String, PathBuf)>> {
-    Ok(fs::read_dir(pool_base)?
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            let arch = entry.file_name();
-            if &arch == "source" {
-                None
-            } else {
-                let path = pool_base.join(&arch);
-                let arch = match arch.to_str().unwrap() {
-                    "binary-amd64" => "amd64",
-                    "binary-arm64" => "arm64",
-                    "binary-armel" => "armel",
-                    "binary-armhf" => "armhf",
-                    "binary-i386" => "i386",
-                    "binary-mips" => "mips",
-                    "binary-mipsel" => "mipsel",
-                    "binary-mips64el" => "mips64el",
-                    "binary-ppc64el" => "ppc64el",
-                    "binary-s390x" => "s390x",
-                    "binary-all" => "all",
-                    arch => panic!("unsupported architecture: {}", arch),
-                };
+/// Collects the `binary-<arch>` pools of a component.
+///
+/// A pool of an architecture which the component is not configured to publish is an error, as
+/// its packages would otherwise be silently left out of the indexes.
+fn binary_suites(config: &Config, component: &str, pool_base: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let architectures = config.architectures_of(component);
+    let mut suites = Vec::new();
+    for entry in fs::read_dir(pool_base)? {
+        let entry = entry?;
+        let dir = entry.file_name();
+        let arch = match dir.to_str() {
+            Some(dir) if dir.starts_with("binary-") => &dir[7..],
+            _ => continue
+        };
+
+        if arch != "all" && !architectures.iter().any(|a| a == arch) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} holds packages for {}, which is not configured for the {} component",
+                    entry.path().display(),
+                    arch,
+                    component
+                )
+            ));
+        }
 
-                Some((arch.to_owned(), path))
-            }
-        }).collect())
+        suites.push((arch.to_owned(), entry.path()));
+    }
+
+    Ok(suites)
+}
+
+type ProcessedResults = Vec<io::Result<(
+    PackageEntry,
+    ContentsEntry,
+    Option<AppStreamEntry>,
+    debian::Arch,
+    debian::Component,
+    CacheKey
+)>>;
+
+/// Parses each `.dsc` file in the source pool of a component into an entry for the Sources index.
+fn source_entries(pool_base: &Path) -> io::Result<Vec<SourceEntry>> {
+    let source_pool = pool_base.join("source");
+    if ! source_pool.exists() {
+        return Ok(Vec::new());
+    }
+
+    let dsc_files = WalkDir::new(&source_pool)
+        .into_iter()
+        .flat_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.file_name().to_str().map_or(false, |e| e.ends_with(".dsc")))
+        .map(|e| e.path().to_path_buf())
+        .collect::<Vec<PathBuf>>();
+
+    dsc_files.into_par_iter()
+        .map(|dsc_path| {
+            info!("processing source package {:?}", dsc_path);
+            let dsc = misc::read_to_string(&dsc_path)?;
+            let control = deb822::parse(&dsc).into_iter().next().ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not contain a control paragraph", dsc_path.display())
+            ))?;
+
+            Ok(SourceEntry {
+                control,
+                directory: dsc_path.parent().unwrap().to_path_buf(),
+                dsc: dsc_path.file_name().unwrap().to_str().unwrap().to_owned(),
+                size: dsc_path.metadata()?.len(),
+                checksums: checksums(&dsc_path)?,
+            })
+        })
+        .collect()
 }
 
-type ProcessedResults = Vec<io::Result<(PackageEntry, ContentsEntry, debian::Arch, debian::Component)>>;
+/// The archives within the directory of a binary pool, keyed by the name and architecture of
+/// each package, which may differ from the architecture of the directory for `all` packages.
+pub(crate) type PoolVersions = HashMap<(String, String), Vec<(String, PathBuf)>>;
+
+pub(crate) fn pool_versions(path: &Path, arch: &str) -> PoolVersions {
+    let mut archives = PoolVersions::new();
+
+    // An iterator that returns debian archives found in the path.
+    let deb_iter = misc::walk_debs(path, misc::INCLUDE_DDEB | misc::INCLUDE_UDEB)
+        .filter(|e| !e.file_type().is_dir())
+        .map(|e| e.path().to_path_buf());
 
+    for package in deb_iter {
+        if let Some((name, version)) = get_debian_package_info(&package) {
+            let package_arch = package.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.rsplit('_').next())
+                .unwrap_or(arch)
+                .to_owned();
+
+            archives.entry((name, package_arch)).or_insert_with(Vec::new).push((version, package));
+        }
+    }
+
+    archives
+}
+
+/// Splits the archives into the newest versions of each package, which are published, and the
+/// older versions beyond the limit of the package, which are left within the pool.
+///
+/// The limit is given the name of the package and the path of its newest archive.
+pub(crate) fn select_versions<F>(archives: PoolVersions, limit: F) -> io::Result<(Vec<(String, PathBuf)>, Vec<PathBuf>)>
+    where F: Fn(&str, &Path) -> io::Result<usize>
+{
+    let mut published = Vec::new();
+    let mut unpublished = Vec::new();
+    for ((name, _), mut versions) in archives {
+        // Newest versions first.
+        versions.sort_by(|a, b| compare_versions(&b.0, &a.0));
+        let limit = limit(&name, &versions[0].1)?;
+        for (version, path) in versions.drain(limit.min(versions.len())..) {
+            info!("not publishing {} {}, as newer versions are published", name, version);
+            debug!("unpublished archive: {}", path.display());
+            unpublished.push(path);
+        }
+
+        published.extend(versions);
+    }
+
+    Ok((published, unpublished))
+}
+
+/// The number of versions of a package which are published, where the name is that which
+/// `get_debian_package_info` returns for the archive.
+pub(crate) fn publish_limit(config: &Config, name: &str, archive: &Path) -> io::Result<usize> {
+    let name = &name[..name.find('_').unwrap_or(name.len())];
+
+    // The control file is only read when the limit may depend upon the source of the package.
+    let source = if config.has_source_publish_versions() {
+        DebArchive::new(archive)?.control_map()?
+            .get("Source")
+            .and_then(|source| source.split_whitespace().next())
+            .map(String::from)
+    } else {
+        None
+    };
+
+    Ok(config.publish_versions_of(name, source.as_ref().map(String::as_str)))
+}
+
+/// Generates the dist files of each component, and returns the entries that should be cached.
+///
+/// Archives which have not changed since they were recorded in the cache are not processed again.
 pub(crate) fn dists(
     config: &Config,
     dist_base: &str,
     pool_base: &Path,
     components: &[String],
-) -> io::Result<()> {
+    cache: &DistCache,
+) -> io::Result<DistCache> {
     info!("generating dist archives");
 
     let origin = &config.origin;
 
     // Collect the entries for each architecture of each component.
     let entries = components.par_iter().map(|component| {
+        let suites = match binary_suites(config, component, &pool_base.join(&component)) {
+            Ok(suites) => suites,
+            Err(why) => return vec![vec![Err(why)]]
+        };
+
         // Collect the entries for each architecture of this component
-        binary_suites(&pool_base.join(&component)).unwrap()
-            .into_par_iter()
+        suites.into_par_iter()
             .map(|(arch, path)| {
-                // Collect a list of packages to process for this architecture.
-                // This list will have older entries filtered.
-                let mut archives: HashMap<String, (String, PathBuf)> = HashMap::new();
-
-                // An iterator that returns debian archives found in the path.
-                let deb_iter = misc::walk_debs(&path, true)
-                    .filter(|e| !e.file_type().is_dir())
-                    .map(|e| e.path().to_path_buf());
-
-                for package in deb_iter {
-                    if let Some((name, version)) = get_debian_package_info(&package) {
-                        match archives.entry(name) {
-                            Entry::Occupied(mut entry) => {
-                                if compare_versions(&entry.get().0, &version) == Ordering::Less {
-                                    eprintln!("replacing {} with {}", entry.get().0, &version);
-                                    entry.insert((version, package));
-                                }
-                            }
-                            Entry::Vacant(mut entry) => {
-                                entry.insert((version, package));
-                            }
-                        }
-                    }
-                }
+                // Only the newest versions of each package are published.
+                let selection = select_versions(pool_versions(&path, &arch), |name, newest| {
+                    publish_limit(config, name, newest)
+                });
+
+                let archives = match selection {
+                    Ok((archives, _)) => archives,
+                    Err(why) => return vec![Err(why)]
+                };
 
                 // Collect the entries for this architecture of this component
                 archives.into_par_iter()
-                    .map(|(_, (_, debian_entry))| {
-                        info!("processing contents of {:?}", debian_entry);
-
+                    .map(|(_, debian_entry)| {
                         let arch: &str = &arch;
                         let component: &str = &component;
 
+                        let key = CacheKey::new(&debian_entry)?;
+                        if let Some((package_entry, contents_entry, appstream)) = cache.get(&debian_entry, key) {
+                            debug!("using cached contents of {:?}", debian_entry);
+                            return Ok((package_entry, contents_entry, appstream, arch.to_owned(), component.to_owned(), key));
+                        }
+
+                        info!("processing contents of {:?}", debian_entry);
+
                         // Open the Debian archive, and get the IDs & required codecs for the inner control and data archives.
                         let archive = DebArchive::new(&debian_entry)?;
                         // Open the control file within the control archive and read each key / value pair into a map.
@@ -243,8 +451,7 @@ pub(crate) fn dists(
 
                         // The Contents archive requires that we know the package and section keys for each Debian package beforehand.
                         let package_name = match (control.get("Package"), control.get("Section")) {
-                            (Some(ref package), Some(ref section)) if component == "main" => [section, "/", package].concat(),
-                            (Some(ref package), Some(ref section)) => [component, "/", section, "/", package].concat(),
+                            (Some(ref package), Some(ref section)) => [section, "/", package].concat(),
                             _ => {
                                 return Err(io::Error::new(
                                     io::ErrorKind::Other,
@@ -255,51 +462,39 @@ pub(crate) fn dists(
 
                         // Now get a listing of all the files for the Contents archive.
                         let mut files: Vec<PathBuf> = Vec::new();
+                        // Along with the metainfo, desktop entries, and icons for AppStream.
+                        let mut appstream = AppStreamFiles::default();
 
                         // Runs each scope in parallel to generate the contents and checksums.
-                        let (content_res, ((sha1_res, sha256_res), (sha512_res, md5_res))) = {
-                            let path = &debian_entry;
-                            // TODO: use bus_writer instead of reading the same file in each thread.
-                            let generate_hashes = || {
-                                rayon::join(
-                                    || rayon::join(
-                                        || File::open(path).and_then(hasher::<Sha1, File>),
-                                        || File::open(path).and_then(hasher::<Sha256, File>),
-                                    ),
-                                    || rayon::join(
-                                        || File::open(path).and_then(hasher::<Sha512, File>),
-                                        || File::open(path).and_then(hasher::<Md5, File>),
-                                    )
-                                )
-                            };
-
-                            rayon::join(
-                                || archive.data(|entry| {
-                                    let path = entry.path()?;
-                                    files.push(path.to_path_buf());
-                                    Ok(())
-                                }),
-                                generate_hashes
-                            )
-                        };
+                        let (content_res, checksums_res) = rayon::join(
+                            || archive.data(|entry| {
+                                let path = entry.path()?.to_path_buf();
+                                appstream.collect(&path, entry)?;
+                                files.push(path);
+                                Ok(())
+                            }),
+                            || checksums(&debian_entry)
+                        );
 
                         drop(archive);
                         content_res?;
+                        let checksums = checksums_res?;
                         let package_entry = PackageEntry {
                             control,
                             filename: debian_entry.clone(),
                             size: File::open(&debian_entry).and_then(|file| file.metadata().map(|m| m.len()))?,
-                            md5sum: md5_res?,
-                            sha1: sha1_res?,
-                            sha256: sha256_res?,
-                            sha512: sha512_res?,
+                            md5sum: checksums.md5sum,
+                            sha1: checksums.sha1,
+                            sha256: checksums.sha256,
+                            sha512: checksums.sha512,
                         };
 
-                        let contents_entry = ContentsEntry { package: package_name, files };
+                        let appstream = appstream.into_entry(&package_entry.control["Package"]);
+                        let contents_entry = ContentsEntry { component: component.to_owned(), package: package_name, files };
                         let arch: String = arch.to_owned();
                         let component: String = component.to_owned();
 
-                        Ok((package_entry, contents_entry, arch, component))
+                        Ok((package_entry, contents_entry, appstream, arch, component, key))
                     }).collect::<ProcessedResults>()
         }).collect::<Vec<ProcessedResults>>()
     }).collect::<Vec<Vec<ProcessedResults>>>();
@@ -311,8 +506,24 @@ pub(crate) fn dists(
     // Validate the results of each parallel process, and collect them in a manner so that they
     // may be used for further parallel processing and compression.
     let mut entries_map: debian::Entries = HashMap::new();
+    let mut udebs_map: debian::Entries = HashMap::new();
+    let mut appstream_map: debian::AppStreamEntries = HashMap::new();
+    let mut new_cache = DistCache::default();
     for result in entries {
-        let (package, contents, arch, component) = result?;
+        let (package, contents, appstream, arch, component, key) = result?;
+        new_cache.insert(package.filename.clone(), key, package.clone(), contents.clone(), appstream.clone());
+
+        if let Some(appstream) = appstream {
+            appstream_map.entry(arch.clone())
+                .or_insert_with(HashMap::new)
+                .entry(component.clone())
+                .or_insert_with(Vec::new)
+                .push(appstream);
+        }
+
+        // Installer archives are listed within their own indexes.
+        let is_udeb = package.filename.extension().map_or(false, |ext| ext == &DEB_INSTALLER_EXTENSION[1..]);
+        let entries_map = if is_udeb { &mut udebs_map } else { &mut entries_map };
 
         match entries_map.entry(arch) {
             Entry::Occupied(mut entry) => {
@@ -337,8 +548,74 @@ pub(crate) fn dists(
         }
     }
 
+    // Every configured architecture of a component has a Packages index, even when it is empty.
+    for component in components {
+        let architectures = config.architectures_of(component).iter().map(String::as_str);
+        let all = if config.arch_all.separate() { Some("all") } else { None };
+        for arch in architectures.chain(all) {
+            entries_map.entry(arch.to_owned())
+                .or_insert_with(|| (HashMap::new(), Vec::new()))
+                .0.entry(component.clone())
+                .or_insert_with(Vec::new);
+        }
+    }
+
+    // Collect the source packages of each component in parallel.
+    let sources = components.par_iter()
+        .map(|component| {
+            source_entries(&pool_base.join(&component))
+                .map(|entries| (component.clone(), entries))
+        })
+        .filter(|result| result.as_ref().ok().map_or(true, |&(_, ref entries)| !entries.is_empty()))
+        .collect::<io::Result<SourceEntries>>()?;
+
     let destination = &Path::new(dist_base);
-    let dist_files = DistFiles::new(destination, entries_map);
-    // Re-enable duplicates checking.
-    dist_files.compress_and_release(config, origin, None)
+    let mut dist_files = DistFiles::new(destination, entries_map, udebs_map, sources, appstream_map);
+    dist_files.check_for_duplicates(config.file_conflicts)?;
+    dist_files.merge_arch_all(config);
+    dist_files.compress_and_release(config, origin, None)?;
+
+    Ok(new_cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(name: &str, version: &str, arch: &str) -> (String, PathBuf) {
+        let path = ["pool/bionic/main/binary-", arch, "/", &name[..1], "/", name, "/", name, "_", version, "_", arch, ".deb"].concat();
+        (version.to_owned(), PathBuf::from(path))
+    }
+
+    #[test]
+    fn newest_versions() {
+        let mut archives = PoolVersions::new();
+        archives.insert(("foo".into(), "amd64".into()), vec![
+            archive("foo", "1.0", "amd64"),
+            archive("foo", "1.10", "amd64"),
+            archive("foo", "1.9", "amd64"),
+        ]);
+        archives.insert(("foo".into(), "all".into()), vec![archive("foo", "1.0", "all")]);
+        archives.insert(("bar".into(), "amd64".into()), vec![
+            archive("bar", "2.0", "amd64"),
+            archive("bar", "1.5", "amd64"),
+        ]);
+
+        let (mut published, mut unpublished) = select_versions(archives, |name, newest| {
+            assert!(newest.to_str().unwrap().contains(&[name, "_"].concat()));
+            Ok(if name == "foo" { 2 } else { 1 })
+        }).unwrap();
+
+        published.sort_by(|a, b| a.1.cmp(&b.1));
+        unpublished.sort();
+
+        assert_eq!(published, vec![
+            archive("foo", "1.0", "all"),
+            archive("bar", "2.0", "amd64"),
+            archive("foo", "1.10", "amd64"),
+            archive("foo", "1.9", "amd64"),
+        ]);
+
+        assert_eq!(unpublished, vec![archive("bar", "1.5", "amd64").1, archive("foo", "1.0", "amd64").1]);
+    }
 }
@@ -1,5 +1,5 @@
 use config::Config;
-use misc::{is_deb, INCLUDE_DDEB, INCLUDE_SRCS};
+use misc::{is_deb, INCLUDE_DDEB, INCLUDE_SRCS, INCLUDE_UDEB};
 use std::io::{self, Error};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -41,7 +41,7 @@ fn inner_migrate(config: &Config, packages: &[&str], from_component: &str, to_co
             .filter_entry(|e| match e.depth() {
                 1 | 2 => true,
                 3 => &e.file_name() == package,
-                4 => is_deb(e, INCLUDE_DDEB | INCLUDE_SRCS),
+                4 => is_deb(e, INCLUDE_DDEB | INCLUDE_SRCS | INCLUDE_UDEB),
                 _ => false
             })
             .flat_map(|e| e.ok())
@@ -1,18 +1,29 @@
 mod build;
+mod cache;
 mod download;
 mod generate;
 mod migrate;
 mod pool;
 mod prepare;
+mod query;
+mod snapshot;
+mod verify;
 mod version;
 
 pub use self::migrate::migrate;
-
-use config::Config;
+pub use self::query::{list, rdepends, show};
+pub use self::snapshot::{create_snapshot, list_snapshots, publish_snapshot};
+pub use self::verify::verify;
+
+use config::{Config, Installability};
+use self::cache::DistCache;
+use debian;
+use misc;
 use misc::remove_empty_directories_from;
 use rayon;
-use rayon::prelude::*;
+use signer;
 use std::{env, fs, io};
+use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 
@@ -93,6 +104,14 @@ impl<'a> Repo<'a> {
 
 #[derive(Debug, Fail)]
 pub enum ReleaseError {
+    #[fail(display = "failed to link the alias at {:?}: {}", path, why)]
+    Alias { path: PathBuf, why: io::Error },
+    #[fail(display = "failed to write dist cache to {:?}: {}", path, why)]
+    Cache { path: PathBuf, why: io::Error },
+    #[fail(display = "failed to link indexes into by-hash directories: {}", why)]
+    ByHash { why: io::Error },
+    #[fail(display = "failed to generate pdiffs for indexes: {}", why)]
+    PDiff { why: io::Error },
     #[fail(display = "failed to collect component names from {:?}", pool)]
     Components { pool: PathBuf, why: io::Error },
     #[fail(display = "failed to generate distribution files for {}: {}", suite, why)]
@@ -103,28 +122,146 @@ pub enum ReleaseError {
     DistRemoval { path: PathBuf, why: io::Error },
     #[fail(display = "failed to generate InRelease file: {}", why)]
     InRelease { why: io::Error },
+    #[fail(display = "failed to check the installability of packages: {}", why)]
+    Installability { why: io::Error },
+    #[fail(display = "there is no previous generation of dists for {} to roll back to", suite)]
+    NoPreviousGeneration { suite: String },
     #[fail(display = "pool cleanup failure at {:?}: {}", path, why)]
     PoolCleanup { path: PathBuf, why: io::Error },
+    #[fail(display = "there are no published dists at {:?}", path)]
+    NotPublished { path: PathBuf },
+    #[fail(display = "failed to publish dists to {:?}: {}", path, why)]
+    Publish { path: PathBuf, why: io::Error },
     #[fail(display = "failed to generate Release.gpg file: {}", why)]
     ReleaseGPG { why: io::Error },
-    #[fail(display = "failed to generate source index: {}", why)]
-    Source { why: io::Error },
+    #[fail(display = "failed to refresh the release file at {:?}: {}", path, why)]
+    Refresh { path: PathBuf, why: io::Error },
+    #[fail(display = "failed to initialize the signer: {}", why)]
+    Signer { why: io::Error },
+    #[fail(display = "failed to create staging directory at {:?}: {}", path, why)]
+    Staging { path: PathBuf, why: io::Error },
+    #[fail(display = "found {} packages which cannot be installed", count)]
+    Uninstallable { count: usize },
+    #[fail(display = "validation of the dists staged at {:?} failed: {}", path, why)]
+    Validation { path: PathBuf, why: io::Error },
+}
+
+/// The location of the dist cache of a suite, relative to the root of the project.
+fn dist_cache_path(suite: &str) -> PathBuf {
+    PathBuf::from(["cache/dists/", suite, ".cache"].concat())
+}
+
+/// Removes the dist cache of a suite, so that every archive in the pool will be processed again.
+pub fn clear_dist_cache(suite: &str) -> io::Result<()> {
+    cache::clear(&dist_cache_path(suite))
 }
 
 /// Generate the dist release files from the existing binary and source files.
+///
+/// The dist files are generated within a staging directory, which is only swapped with the
+/// published dists after it has been signed and validated. The dists that were replaced are
+/// kept, so that the suite can be rolled back to them.
 pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
+    let cache_path = env::current_dir()
+        .expect("unable to get current dir")
+        .join(dist_cache_path(&sources.archive));
+
+    // Keys are loaded before switching directories, as their paths are relative to the project.
+    let signer = signer::from_config(sources)
+        .map_err(|why| ReleaseError::Signer { why })?;
+
     env::set_current_dir("repo").expect("unable to switch dir to repo");
 
-    let base = ["dists/", &sources.archive].concat();
+    let dists = PathBuf::from(["dists/", &sources.archive].concat());
+    let previous = PathBuf::from(["previous/", &sources.archive].concat());
+    let cache = DistCache::load(&cache_path);
+
+    let base = stage_release_files(sources, &*signer, &dists, &cache, Some(&cache_path))?;
+
+    publish(&base, &dists, &previous)
+        .map_err(|why| ReleaseError::Publish { path: dists.clone(), why })?;
+
+    let links = sources.dist_links();
+    for name in &links {
+        let link = PathBuf::from(["dists/", name].concat());
+        link_dists(&sources.archive, &link)
+            .map_err(|why| ReleaseError::Alias { path: link.clone(), why })?;
+    }
+
+    unlink_stale_dists(&sources.archive, &links)
+        .map_err(|why| ReleaseError::Alias { path: PathBuf::from("dists"), why })
+}
+
+/// Removes the links to the dists of the suite which are no longer among its aliases.
+fn unlink_stale_dists(archive: &str, links: &[&str]) -> io::Result<()> {
+    for entry in fs::read_dir("dists")? {
+        let entry = entry?;
+        if !entry.file_type()?.is_symlink() || fs::read_link(entry.path())? != Path::new(archive) {
+            continue
+        }
+
+        let stale = entry.file_name().to_str().map_or(true, |name| !links.contains(&name));
+        if stale {
+            info!("removing stale alias {}", entry.path().display());
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Links the alias to the dists of the suite, replacing any link that was there before.
+fn link_dists(archive: &str, link: &Path) -> io::Result<()> {
+    match link.symlink_metadata() {
+        Ok(ref metadata) if metadata.file_type().is_symlink() => {
+            if fs::read_link(link)? == Path::new(archive) {
+                return Ok(());
+            }
+
+            fs::remove_file(link)?;
+        }
+        Ok(_) => return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "a directory which is not an alias already exists there"
+        )),
+        Err(_) => ()
+    }
+
+    info!("linking {} to {}", link.display(), archive);
+    symlink(archive, link)
+}
+
+/// Generates, signs, and validates the dist files of the suite's pool within its staging
+/// directory, returning the path of the staged dists. The working directory must be `repo/`.
+///
+/// The cache is only written when a path is given for it.
+fn stage_release_files(
+    sources: &Config,
+    signer: &signer::Signer,
+    dists: &Path,
+    cache: &DistCache,
+    cache_path: Option<&Path>
+) -> Result<PathBuf, ReleaseError> {
+    let base = ["staging/", &sources.archive].concat();
     let pool = ["pool/", &sources.archive, "/"].concat();
     let pool_path = &Path::new(&pool);
 
     {
         let base = &Path::new(&base);
         if base.exists() {
+            // Remnants of a generation which failed to be published.
             fs::remove_dir_all(&base)
                 .map_err(|why| ReleaseError::DistRemoval { path: base.to_path_buf(), why })?;
         }
+
+        fs::create_dir_all(&base)
+            .map_err(|why| ReleaseError::Staging { path: base.to_path_buf(), why })?;
+
+        // Previous generations of the by-hash indexes must outlive the dists they belonged to.
+        if sources.by_hash.is_some() && dists.exists() {
+            generate::link_by_hash(dists, &base)
+                .map_err(|why| ReleaseError::ByHash { why })?;
+        }
     }
 
     remove_empty_directories_from(pool_path)
@@ -134,24 +271,52 @@ pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
     let in_release = PathBuf::from([&base, "/InRelease"].concat());
     let release_gpg = PathBuf::from([&base, "/Release.gpg"].concat());
 
-    let components = collect_components(pool_path, &base).map_err(|why| {
+    let components = collect_components(sources, pool_path, &base).map_err(|why| {
         ReleaseError::Components { pool: pool_path.to_path_buf(), why }
     })?;
 
     // Generates the dist directory's archives in parallel.
-    generate::dists(sources, &base, pool_path, &components)
+    let mut cache = generate::dists(sources, &base, pool_path, &components, cache)
         .map_err(|why| ReleaseError::DistGeneration {
             suite: sources.archive.clone(),
             why
         })?;
 
-    // TODO: Merge this functionality with generate::dists
-    // Then write the source archives in the dist directory
-    components.par_iter().map(|component| {
-        let pool = [&pool, component.as_str()].concat();
-        generate::sources_index(&component, &base, &pool)
-            .map_err(|why| ReleaseError::Source { why })
-    }).collect::<Result<(), ReleaseError>>()?;
+    if let Some(cache_path) = cache_path {
+        cache.save(cache_path)
+            .map_err(|why| ReleaseError::Cache { path: cache_path.to_path_buf(), why })?;
+    }
+
+    if sources.installability != Installability::Ignore {
+        // The working directory is `repo/`, and the paths of the config are relative to the project.
+        let upstream = sources.upstream_packages.iter()
+            .map(|path| Path::new("..").join(path))
+            .collect::<Vec<PathBuf>>();
+
+        let uninstallable = debian::uninstallable_packages(Path::new(&base), &sources.all_architectures(), &upstream)
+            .map_err(|why| ReleaseError::Installability { why })?;
+
+        for package in &uninstallable {
+            match sources.installability {
+                Installability::Error => error!("{}", package),
+                _ => warn!("{}", package)
+            }
+        }
+
+        if sources.installability == Installability::Error && !uninstallable.is_empty() {
+            return Err(ReleaseError::Uninstallable { count: uninstallable.len() });
+        }
+    }
+
+    if let Some(history) = sources.pdiffs {
+        generate::pdiffs(dists, &base, history)
+            .map_err(|why| ReleaseError::PDiff { why })?;
+    }
+
+    if let Some(generations) = sources.by_hash {
+        generate::by_hash(&base, generations)
+            .map_err(|why| ReleaseError::ByHash { why })?;
+    }
 
     generate::dists_release(sources, &base, &components)
         .map_err(|why| ReleaseError::DistRelease {
@@ -159,21 +324,149 @@ pub fn generate_release_files(sources: &Config) -> Result<(), ReleaseError> {
             why,
         })?;
 
-    let (inrelease, release) = rayon::join(
+    let (inrelease, release_res) = rayon::join(
+        || {
+            signer.clearsign(&release, &in_release)
+                .map_err(|why| ReleaseError::InRelease { why })
+        },
+        || {
+            signer.detach_sign(&release, &release_gpg)
+                .map_err(|why| ReleaseError::ReleaseGPG { why })
+        }
+    );
+
+    inrelease.and(release_res)?;
+
+    debian::validate(Path::new(&base))
+        .map_err(|why| ReleaseError::Validation { path: PathBuf::from(&base), why })?;
+
+    Ok(PathBuf::from(base))
+}
+
+/// Updates the `Date` and `Valid-Until` fields of the published `Release` file, and signs it
+/// again, without processing the pool or touching any of the indexes.
+///
+/// The new release files are written beside the old ones, and each is renamed into place after
+/// every one of them has been signed.
+pub fn refresh(config: &Config) -> Result<(), ReleaseError> {
+    let signer = signer::from_config(config)
+        .map_err(|why| ReleaseError::Signer { why })?;
+
+    env::set_current_dir("repo").expect("unable to switch dir to repo");
+
+    let dists = PathBuf::from(["dists/", &config.archive].concat());
+    let release = dists.join("Release");
+    if !release.exists() {
+        return Err(ReleaseError::NotPublished { path: dists });
+    }
+
+    info!("refreshing the release files of {}", config.archive);
+    let (date, valid_until) = generate::release_dates(config);
+    let refreshed = misc::read_to_string(&release)
+        .map(|text| debian::refresh_dates(&text, &date, valid_until.as_ref().map(String::as_str)))
+        .map_err(|why| ReleaseError::Refresh { path: release.clone(), why })?;
+
+    let files = ["Release", "InRelease", "Release.gpg"];
+    let new = |name: &str| dists.join([name, ".new"].concat());
+
+    fs::write(new("Release"), refreshed)
+        .map_err(|why| ReleaseError::Refresh { path: new("Release"), why })?;
+
+    let (inrelease, release_res) = rayon::join(
         || {
-            generate::gpg_in_release(&sources.email, &release, &in_release)
+            signer.clearsign(&new("Release"), &new("InRelease"))
                 .map_err(|why| ReleaseError::InRelease { why })
         },
         || {
-            generate::gpg_release(&sources.email, &release, &release_gpg)
+            signer.detach_sign(&new("Release"), &new("Release.gpg"))
                 .map_err(|why| ReleaseError::ReleaseGPG { why })
         }
     );
 
-    inrelease.and(release)
+    if let Err(why) = inrelease.and(release_res) {
+        for name in &files {
+            let _ = fs::remove_file(new(name));
+        }
+
+        return Err(why);
+    }
+
+    for name in &files {
+        fs::rename(new(name), dists.join(name))
+            .map_err(|why| ReleaseError::Refresh { path: dists.join(name), why })?;
+    }
+
+    Ok(())
 }
 
-fn collect_components(pool: &Path, base: &str) -> io::Result<Vec<String>> {
+/// Prints each package of the published dists which cannot be installed, and why.
+pub fn check_installability(config: &Config) -> Result<(), ReleaseError> {
+    let dists = PathBuf::from(["repo/dists/", &config.archive].concat());
+    if !dists.exists() {
+        return Err(ReleaseError::NotPublished { path: dists });
+    }
+
+    let uninstallable = debian::uninstallable_packages(&dists, &config.all_architectures(), &config.upstream_packages)
+        .map_err(|why| ReleaseError::Installability { why })?;
+
+    for package in &uninstallable {
+        println!("{}", package);
+    }
+
+    if uninstallable.is_empty() {
+        Ok(())
+    } else {
+        Err(ReleaseError::Uninstallable { count: uninstallable.len() })
+    }
+}
+
+/// Swaps the published dists of a suite with the generation that preceded them.
+pub fn rollback(config: &Config) -> Result<(), ReleaseError> {
+    env::set_current_dir("repo").expect("unable to switch dir to repo");
+
+    let dists = PathBuf::from(["dists/", &config.archive].concat());
+    let previous = PathBuf::from(["previous/", &config.archive].concat());
+
+    if !previous.exists() {
+        return Err(ReleaseError::NoPreviousGeneration { suite: config.archive.clone() });
+    }
+
+    info!("rolling back {} to the previous generation of dists", config.archive);
+    let result = if dists.exists() {
+        misc::exchange(&previous, &dists)
+    } else {
+        fs::rename(&previous, &dists)
+    };
+
+    result.map_err(|why| ReleaseError::Publish { path: dists, why })
+}
+
+/// Moves the staged dists into place, and keeps the dists that they replaced.
+fn publish(staging: &Path, dists: &Path, previous: &Path) -> io::Result<()> {
+    info!("publishing {} to {}", staging.display(), dists.display());
+
+    if previous.exists() {
+        fs::remove_dir_all(previous)?;
+    }
+
+    if !dists.exists() {
+        fs::create_dir_all(dists.parent().unwrap())?;
+        return fs::rename(staging, dists);
+    }
+
+    fs::create_dir_all(previous.parent().unwrap())?;
+
+    // Exchanging the directories ensures that clients never observe a missing suite.
+    if let Err(why) = misc::exchange(staging, dists) {
+        warn!("unable to atomically exchange {} with {}: {}", staging.display(), dists.display(), why);
+        fs::rename(dists, previous)?;
+        return fs::rename(staging, dists);
+    }
+
+    fs::rename(staging, previous)
+}
+
+fn collect_components(config: &Config, pool: &Path, base: &str) -> io::Result<Vec<String>> {
     let mut components = Vec::new();
 
     for component in pool.read_dir()? {
@@ -181,23 +474,17 @@ fn collect_components(pool: &Path, base: &str) -> io::Result<Vec<String>> {
             if component.path().is_dir() {
                 let component = component.file_name();
                 let component = component.to_str().unwrap();
-                for arch in &[
-                    "binary-amd64",
-                    "binary-arm64",
-                    "binary-armel",
-                    "binary-armhf",
-                    "binary-i386",
-                    "binary-mips",
-                    "binary-mipsel",
-                    "binary-mips64el",
-                    "binary-ppc64el",
-                    "binary-s390x",
-                    "binary-all",
-                    "source",
-                ] {
-                    let _ = fs::create_dir_all([&base, "/", component, "/", arch].concat());
+                let all = if config.arch_all.separate() { Some("all") } else { None };
+                let architectures = config.architectures_of(component).iter()
+                    .map(String::as_str)
+                    .chain(all);
+
+                for arch in architectures {
+                    let _ = fs::create_dir_all([&base, "/", component, "/binary-", arch].concat());
                 }
 
+                let _ = fs::create_dir_all([&base, "/", component, "/source"].concat());
+
                 components.push(component.to_owned());
             }
         }
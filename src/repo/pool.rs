@@ -1,17 +1,26 @@
 use std::{io, fs};
-use std::path::{Path, PathBuf};
-use debian::DEB_SOURCE_EXTENSIONS;
+use std::path::Path;
+use debian::{DEB_INSTALLER_EXTENSION, DEB_SOURCE_EXTENSIONS};
 use misc;
 
 pub const KEEP_SOURCE: u8 = 1;
 pub const ARCHIVES_ONLY: u8 = 2;
 
-pub fn mv_to_pool<P: AsRef<Path>>(path: P, suite: &str, component: &str, flags: u8, filter: Option<&str>) -> io::Result<()> {
+pub fn mv_to_pool<P: AsRef<Path>>(
+    path: P,
+    suite: &str,
+    component: &str,
+    architectures: &[String],
+    flags: u8,
+    filter: Option<&str>
+) -> io::Result<()> {
     info!("moving items in {} to pool at {}/{}", path.as_ref().display(), suite, component);
     pool(
         path.as_ref(),
+        Path::new("repo/pool"),
         suite,
         component,
+        architectures,
         flags,
         |src, dst| if flags & KEEP_SOURCE != 0 || !is_source(src) {
             fs::rename(src, dst)
@@ -22,20 +31,22 @@ pub fn mv_to_pool<P: AsRef<Path>>(path: P, suite: &str, component: &str, flags:
     )
 }
 
-fn is_source(src: &Path) -> bool {
+pub(crate) fn is_source(src: &Path) -> bool {
     let path = src.to_str().unwrap();
     path.ends_with(".dsc") || path.ends_with(".tar.gz") || path.ends_with(".tar.xz")
 }
 
-fn is_archive(src: &Path) -> bool {
+pub(crate) fn is_archive(src: &Path) -> bool {
     let path = src.to_str().unwrap();
-    path.ends_with(".deb") || path.ends_with(".ddeb")
+    path.ends_with(".deb") || path.ends_with(".ddeb") || path.ends_with(DEB_INSTALLER_EXTENSION)
 }
 
 fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
     path: &Path,
+    root: &Path,
     suite: &str,
     component: &str,
+    architectures: &[String],
     flags: u8,
     action: F,
     filter: Option<&str>,
@@ -58,23 +69,30 @@ fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
                 }
             }
 
+            let mut package = match filename.find('_') {
+                Some(pos) => &filename[..pos],
+                None => {
+                    warn!("{} is not a package file -- not migrating to pool", path.display());
+                    continue
+                }
+            };
+
             info!("migrating {} to pool", path.display());
-            let mut package = &filename[..filename.find('_').unwrap_or(0)];
 
             let is_source = DEB_SOURCE_EXTENSIONS.into_iter().any(|ext| filename.ends_with(&ext[1..]));
             let destination = if is_source {
-                PathBuf::from(
-                    ["repo/pool/", suite, "/", component, "/source/", &package[0..1], "/", package].concat()
+                root.join(
+                    [suite, "/", component, "/source/", &package[0..1], "/", package].concat()
                 )
             } else {
                 if package.ends_with("-dbgsym") {
                     package = &package[..package.len() - 7];
                 }
 
-                let arch = misc::get_arch_from_stem(filestem);
+                let arch = misc::get_arch_from_stem(filestem, architectures);
 
-                PathBuf::from(
-                    ["repo/pool/", suite, "/", component, "/binary-", arch, "/", &package[0..1], "/", package].concat(),
+                root.join(
+                    [suite, "/", component, "/binary-", arch, "/", &package[0..1], "/", package].concat(),
                 )
             };
 
@@ -86,3 +104,46 @@ fn pool<F: Fn(&Path, &Path) -> io::Result<()>>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile;
+
+    #[test]
+    fn binaries_named_apart_from_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("build/bionic/.out/libfoo-amd64");
+        let root = dir.path().join("repo/pool");
+        fs::create_dir_all(output.join("libfoo/debian")).unwrap();
+
+        let files = [
+            "libfoo_1.0.dsc",
+            "libfoo_1.0_amd64.changes",
+            "libfoo-dev_1.0_amd64.deb",
+            "libfoo-dev-dbgsym_1.0_amd64.ddeb",
+            "foo-utils_1.0_all.deb",
+        ];
+
+        for file in &files {
+            fs::write(output.join(file), file).unwrap();
+        }
+
+        let architectures = vec!["amd64".to_owned()];
+        pool(&output, &root, "bionic", "main", &architectures, KEEP_SOURCE, |src, dst| fs::rename(src, dst), None).unwrap();
+
+        for path in &[
+            "bionic/main/source/l/libfoo/libfoo_1.0.dsc",
+            "bionic/main/binary-amd64/l/libfoo/libfoo_1.0_amd64.changes",
+            "bionic/main/binary-amd64/l/libfoo-dev/libfoo-dev_1.0_amd64.deb",
+            "bionic/main/binary-amd64/l/libfoo-dev/libfoo-dev-dbgsym_1.0_amd64.ddeb",
+            "bionic/main/binary-all/f/foo-utils/foo-utils_1.0_all.deb",
+        ] {
+            assert!(root.join(path).exists(), "{} was not moved to the pool", path);
+        }
+
+        // The copy of the source which was built from is left behind.
+        assert!(output.join("libfoo/debian").exists());
+        assert_eq!(output.read_dir().unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,274 @@
+use config::Config;
+use deb_version::compare_versions;
+use debarchive::Archive as DebArchive;
+use debian::{deb822, read_index, Control};
+use glob::Pattern;
+use serde_json;
+use std::io;
+use std::path::{Path, PathBuf};
+use super::pool::is_archive;
+use walkdir::WalkDir;
+
+/// The fields of a package which refer to packages that it depends upon.
+const DEPENDENCY_FIELDS: &[&str] = &["Pre-Depends", "Depends", "Recommends", "Suggests"];
+
+/// A package which is published within the suite.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Published {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+    pub component: String,
+    /// The path of the archive, relative to `repo/`.
+    pub filename: String,
+    pub control: Control,
+}
+
+/// A package which depends upon the package that was queried.
+#[derive(Debug, Serialize)]
+pub struct ReverseDependency<'a> {
+    pub package: &'a str,
+    pub version: &'a str,
+    pub architecture: &'a str,
+    pub component: &'a str,
+    pub field: &'static str,
+    pub relation: String,
+}
+
+/// Prints the name, version, architecture, and component of each published package which
+/// matches the filters.
+pub fn list(
+    config: &Config,
+    component: Option<&str>,
+    arch: Option<&str>,
+    pattern: Option<&str>,
+    json: bool
+) -> io::Result<()> {
+    let pattern = match pattern {
+        Some(pattern) => Some(Pattern::new(pattern).map_err(|why| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid package pattern '{}': {}", pattern, why)
+        ))?),
+        None => None
+    };
+
+    let packages = published(config)?.into_iter()
+        .filter(|package| component.map_or(true, |component| package.component == component))
+        .filter(|package| arch.map_or(true, |arch| package.architecture == arch))
+        .filter(|package| pattern.as_ref().map_or(true, |pattern| pattern.matches(&package.package)))
+        .collect::<Vec<Published>>();
+
+    if json {
+        return print_json(&config.archive, &packages);
+    }
+
+    for package in &packages {
+        println!("{} {} {} {}", package.package, package.version, package.architecture, package.component);
+    }
+
+    Ok(())
+}
+
+/// Prints every published version of the package, along with its component, pool path, and
+/// control fields.
+pub fn show(config: &Config, name: &str, json: bool) -> io::Result<()> {
+    let packages = published(config)?.into_iter()
+        .filter(|package| package.package == name)
+        .collect::<Vec<Published>>();
+
+    if packages.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not published in {}", name, config.archive)
+        ));
+    }
+
+    if json {
+        return print_json(&config.archive, &packages);
+    }
+
+    for package in &packages {
+        println!("Component: {}", package.component);
+        for (key, value) in &package.control {
+            println!("{}: {}", key, value);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Prints each published package which depends upon, recommends, or suggests the package.
+pub fn rdepends(config: &Config, name: &str, json: bool) -> io::Result<()> {
+    let packages = published(config)?;
+    let mut dependents = Vec::new();
+
+    for package in &packages {
+        for &field in DEPENDENCY_FIELDS {
+            let value = match package.control.get(field) {
+                Some(value) => value,
+                None => continue
+            };
+
+            for relation in value.split(',').map(str::trim) {
+                if relation.split('|').any(|alternative| relation_name(alternative) == name) {
+                    dependents.push(ReverseDependency {
+                        package: &package.package,
+                        version: &package.version,
+                        architecture: &package.architecture,
+                        component: &package.component,
+                        field,
+                        relation: relation.to_owned(),
+                    });
+                }
+            }
+        }
+    }
+
+    if json {
+        return print_json(&config.archive, &dependents);
+    }
+
+    for dependent in &dependents {
+        println!(
+            "{} {} {} {}: {}: {}",
+            dependent.package,
+            dependent.version,
+            dependent.architecture,
+            dependent.component,
+            dependent.field,
+            dependent.relation
+        );
+    }
+
+    Ok(())
+}
+
+/// The name of the package within a relation, such as `libc6` of `libc6:any (>= 2.27)`.
+fn relation_name(relation: &str) -> &str {
+    let name = relation.trim().split(|c: char| c.is_whitespace() || c == '(' || c == '[')
+        .next()
+        .unwrap_or("");
+
+    name.split(':').next().unwrap_or(name)
+}
+
+fn print_json<T: ::serde::Serialize>(suite: &str, packages: &[T]) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Output<'a, T: 'a> {
+        suite: &'a str,
+        packages: &'a [T],
+    }
+
+    let output = serde_json::to_string(&Output { suite, packages })
+        .map_err(|why| io::Error::new(io::ErrorKind::Other, format!("{}", why)))?;
+    println!("{}", output);
+    Ok(())
+}
+
+/// Collects the packages which are published within the `Packages` indexes of the suite.
+///
+/// If the dists of the suite have not been generated, the control files of the archives within
+/// the pool are read instead.
+fn published(config: &Config) -> io::Result<Vec<Published>> {
+    let dists = PathBuf::from(["repo/dists/", &config.archive].concat());
+    let mut packages = if dists.exists() {
+        from_indexes(&dists)?
+    } else {
+        info!("{} has no dists, so the pool will be read instead", config.archive);
+        from_pool(&PathBuf::from(["repo/pool/", &config.archive].concat()))?
+    };
+
+    packages.sort_by(|a, b| {
+        a.package.cmp(&b.package)
+            .then_with(|| a.component.cmp(&b.component))
+            .then_with(|| a.architecture.cmp(&b.architecture))
+            .then_with(|| compare_versions(&b.version, &a.version))
+    });
+
+    // Packages of the `all` architecture may be listed within the index of every architecture.
+    packages.dedup_by(|a, b| a.filename == b.filename && a.component == b.component);
+
+    Ok(packages)
+}
+
+/// Reads every `Packages` index within the dists, including those of the debian-installer.
+fn from_indexes(dists: &Path) -> io::Result<Vec<Published>> {
+    let mut packages = Vec::new();
+    let directories = WalkDir::new(dists)
+        .min_depth(2)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "by-hash")
+        .flat_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+        .filter(|entry| entry.file_name().to_str().map_or(false, |name| name.starts_with("binary-")));
+
+    for directory in directories {
+        let path = directory.path();
+        let component = match path.strip_prefix(dists).ok().and_then(|path| path.iter().next()) {
+            Some(component) => component.to_string_lossy().into_owned(),
+            None => continue
+        };
+
+        let index = match read_index(path, "Packages") {
+            Ok(index) => index,
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => continue,
+            Err(why) => return Err(why),
+        };
+
+        for control in deb822::parse(&index) {
+            let filename = control.get("Filename").cloned().unwrap_or_default();
+            packages.push(package(control, component.clone(), filename));
+        }
+    }
+
+    Ok(packages)
+}
+
+/// Reads the control file of every archive within the pool of the suite.
+fn from_pool(pool: &Path) -> io::Result<Vec<Published>> {
+    let mut packages = Vec::new();
+    let archives = WalkDir::new(pool)
+        .min_depth(2)
+        .into_iter()
+        .flat_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && is_archive(entry.path()));
+
+    for entry in archives {
+        let path = entry.path();
+        let component = match path.strip_prefix(pool).ok().and_then(|path| path.iter().next()) {
+            Some(component) => component.to_string_lossy().into_owned(),
+            None => continue
+        };
+
+        let control = DebArchive::new(path)?.control_map()?;
+        let filename = path.strip_prefix("repo").unwrap_or(path).to_string_lossy().into_owned();
+        packages.push(package(control, component, filename));
+    }
+
+    Ok(packages)
+}
+
+fn package(control: Control, component: String, filename: String) -> Published {
+    Published {
+        package: control.get("Package").cloned().unwrap_or_default(),
+        version: control.get("Version").cloned().unwrap_or_default(),
+        architecture: control.get("Architecture").cloned().unwrap_or_default(),
+        component,
+        filename,
+        control,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relation_names() {
+        assert_eq!(relation_name("libc6 (>= 2.27)"), "libc6");
+        assert_eq!(relation_name(" python3:any"), "python3");
+        assert_eq!(relation_name("foo [amd64]"), "foo");
+        assert_eq!(relation_name("bar"), "bar");
+    }
+}
@@ -0,0 +1,301 @@
+use chrono::Utc;
+use config::Config;
+use debian;
+use misc;
+use signer;
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use super::cache::DistCache;
+use super::{dist_cache_path, publish, stage_release_files, ReleaseError};
+use toml;
+use walkdir::WalkDir;
+
+/// The name of the manifest within the directory of each snapshot.
+const MANIFEST: &str = "snapshot.toml";
+
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    #[fail(display = "'{}' is not a valid snapshot name", name)]
+    InvalidName { name: String },
+    #[fail(display = "a snapshot named {} already exists for {}", name, suite)]
+    Exists { suite: String, name: String },
+    #[fail(display = "there is no snapshot named {} for {}", name, suite)]
+    NotFound { suite: String, name: String },
+    #[fail(display = "the pool of the snapshot is missing {:?}", path)]
+    MissingFile { path: PathBuf },
+    #[fail(display = "failed to link {:?} into {:?}: {}", src, dst, why)]
+    Link { src: PathBuf, dst: PathBuf, why: io::Error },
+    #[fail(display = "failed to read snapshot manifest at {:?}: {}", path, why)]
+    ManifestRead { path: PathBuf, why: io::Error },
+    #[fail(display = "failed to write snapshot manifest at {:?}: {}", path, why)]
+    ManifestWrite { path: PathBuf, why: io::Error },
+    #[fail(display = "dist release file generation failed: {}", why)]
+    DistRelease { why: ReleaseError },
+    #[fail(display = "refusing to replace {:?}, which was not published by a snapshot", path)]
+    NotOwned { path: PathBuf },
+}
+
+impl From<ReleaseError> for SnapshotError {
+    fn from(why: ReleaseError) -> Self {
+        SnapshotError::DistRelease { why }
+    }
+}
+
+/// The record of a snapshot, which is stored beside its dists.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub suite: String,
+    /// The date that the snapshot was created, formatted according to RFC 2822.
+    pub created: String,
+    /// The files of the pool, relative to the pool of the snapshot.
+    pub pool: Vec<String>,
+    /// The files of the dists, relative to the dists of the snapshot.
+    pub indexes: Vec<String>,
+    /// Whether the snapshot has been published, and therefore owns its dists.
+    #[serde(default)]
+    pub published: bool,
+}
+
+/// The name of the suite that a snapshot is published as, such as `bionic-2018-10-01`.
+fn snapshot_archive(suite: &str, name: &str) -> String {
+    [suite, "-", name].concat()
+}
+
+/// Snapshot names are used within the names of directories.
+fn validate_name(name: &str) -> Result<(), SnapshotError> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        Err(SnapshotError::InvalidName { name: name.to_owned() })
+    } else {
+        Ok(())
+    }
+}
+
+/// The directory of a snapshot, relative to `repo/`.
+fn snapshot_path(suite: &str, name: &str) -> PathBuf {
+    PathBuf::from(["snapshots/", suite, "/", name].concat())
+}
+
+/// Records the pool of the suite as a snapshot, and generates the dists of that pool.
+///
+/// The files of the pool are hard-linked into `pool/<suite>-<name>/`, so they are not affected
+/// by any later change to the pool of the suite. The dists are stored with the snapshot until
+/// the snapshot is published.
+pub fn create_snapshot(config: &Config, name: &str) -> Result<(), SnapshotError> {
+    validate_name(name)?;
+
+    let cache_path = env::current_dir()
+        .expect("unable to get current dir")
+        .join(dist_cache_path(&config.archive));
+
+    // Keys are loaded before switching directories, as their paths are relative to the project.
+    let signer = signer::from_config(config)
+        .map_err(|why| ReleaseError::Signer { why })?;
+
+    env::set_current_dir("repo").expect("unable to switch dir to repo");
+
+    let archive = snapshot_archive(&config.archive, name);
+    let directory = snapshot_path(&config.archive, name);
+    let suite_pool = PathBuf::from(["pool/", &config.archive].concat());
+    let snapshot_pool = PathBuf::from(["pool/", &archive].concat());
+
+    if directory.exists() || snapshot_pool.exists() {
+        return Err(SnapshotError::Exists { suite: config.archive.clone(), name: name.to_owned() });
+    }
+
+    info!("creating snapshot {} of {}", name, config.archive);
+    let staging = PathBuf::from(["staging/", &archive].concat());
+    let create = || -> Result<(), SnapshotError> {
+        let pool = link_tree(&suite_pool, &snapshot_pool)?;
+
+        let mut snapshot_config = config.clone();
+        snapshot_config.archive = archive.clone();
+        // A snapshot has no history to generate patches from, and is only known by its own name.
+        // As it is never generated again, it must not expire.
+        snapshot_config.pdiffs = None;
+        snapshot_config.valid_for = None;
+        snapshot_config.suite = None;
+        snapshot_config.aliases.clear();
+
+        // The archives are hard links of those which the suite has already processed.
+        let cache = DistCache::load(&cache_path).relocate(&suite_pool, &snapshot_pool);
+        let dists = PathBuf::from(["dists/", &archive].concat());
+        let staged = stage_release_files(&snapshot_config, &*signer, &dists, &cache, None)?;
+
+        let snapshot_dists = directory.join("dists");
+        fs::create_dir_all(&directory)
+            .and_then(|_| fs::rename(&staged, &snapshot_dists))
+            .map_err(|why| SnapshotError::Link { src: staged.clone(), dst: snapshot_dists.clone(), why })?;
+
+        let snapshot = Snapshot {
+            name: name.to_owned(),
+            suite: config.archive.clone(),
+            created: Utc::now().format("%a, %d %b %Y %H:%M:%S UTC").to_string(),
+            pool,
+            indexes: relative_files(&snapshot_dists),
+            published: false,
+        };
+
+        write_manifest(&directory, &snapshot)
+    };
+
+    let result = create();
+
+    // A partial snapshot would prevent the snapshot from being created again.
+    if result.is_err() {
+        for path in &[&snapshot_pool, &staging, &directory] {
+            if path.exists() {
+                if let Err(why) = fs::remove_dir_all(path) {
+                    warn!("failed to remove the partial snapshot at {}: {}", path.display(), why);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Prints the name, creation date, and size of each snapshot of the suite, and whether the
+/// snapshot has been published.
+pub fn list_snapshots(config: &Config) -> Result<(), SnapshotError> {
+    let repo = Path::new("repo");
+    let directory = repo.join("snapshots").join(&config.archive);
+    if !directory.exists() {
+        return Ok(());
+    }
+
+    let mut names = directory.read_dir()
+        .map_err(|why| SnapshotError::ManifestRead { path: directory.clone(), why })?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect::<Vec<String>>();
+
+    names.sort();
+
+    for name in names {
+        let snapshot = read_manifest(&repo.join(snapshot_path(&config.archive, &name)))?;
+        let archive = snapshot_archive(&config.archive, &name);
+        let published = repo.join("dists").join(&archive).exists();
+        println!(
+            "{}: created {} with {} pool files{}",
+            name,
+            snapshot.created,
+            snapshot.pool.len(),
+            if published { ", published as ".to_owned() + &archive } else { String::new() }
+        );
+    }
+
+    Ok(())
+}
+
+/// Publishes the dists that were recorded by the snapshot as `dists/<suite>-<name>`.
+///
+/// The dists are validated before they are published, and every file of the snapshot's pool
+/// must still exist. Dists which were not published by the snapshot, such as those of a suite
+/// that happens to share its name, are never replaced.
+pub fn publish_snapshot(config: &Config, name: &str) -> Result<(), SnapshotError> {
+    validate_name(name)?;
+    env::set_current_dir("repo").expect("unable to switch dir to repo");
+
+    let directory = snapshot_path(&config.archive, name);
+    if !directory.exists() {
+        return Err(SnapshotError::NotFound { suite: config.archive.clone(), name: name.to_owned() });
+    }
+
+    let mut snapshot = read_manifest(&directory)?;
+    let archive = snapshot_archive(&config.archive, name);
+    let pool = PathBuf::from(["pool/", &archive].concat());
+    let dists = PathBuf::from(["dists/", &archive].concat());
+
+    if dists.exists() && !snapshot.published {
+        return Err(SnapshotError::NotOwned { path: dists });
+    }
+
+    for file in &snapshot.pool {
+        let path = pool.join(file);
+        if !path.exists() {
+            return Err(SnapshotError::MissingFile { path });
+        }
+    }
+
+    let staging = PathBuf::from(["staging/", &archive].concat());
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .map_err(|why| ReleaseError::DistRemoval { path: staging.clone(), why })?;
+    }
+
+    // The recorded dists are linked, rather than moved, so that they may be published again.
+    link_tree(&directory.join("dists"), &staging)?;
+
+    debian::validate(&staging)
+        .map_err(|why| ReleaseError::Validation { path: staging.clone(), why })?;
+
+    // Ownership is recorded first, so that a failed publish may be retried.
+    snapshot.published = true;
+    write_manifest(&directory, &snapshot)?;
+
+    info!("publishing snapshot {} of {} as {}", name, config.archive, archive);
+    let previous = PathBuf::from(["previous/", &archive].concat());
+    publish(&staging, &dists, &previous)
+        .map_err(|why| ReleaseError::Publish { path: dists, why })?;
+
+    Ok(())
+}
+
+fn write_manifest(directory: &Path, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+    let path = directory.join(MANIFEST);
+    toml::ser::to_string(snapshot)
+        .map_err(|why| io::Error::new(io::ErrorKind::Other, format!("{}", why)))
+        .and_then(|data| fs::write(&path, data))
+        .map_err(|why| SnapshotError::ManifestWrite { path, why })
+}
+
+fn read_manifest(directory: &Path) -> Result<Snapshot, SnapshotError> {
+    let path = directory.join(MANIFEST);
+    misc::read_to_string(&path)
+        .and_then(|data| toml::from_str(&data).map_err(|why| io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}", why)
+        )))
+        .map_err(|why| SnapshotError::ManifestRead { path, why })
+}
+
+/// Hard-links every file within the source directory into the destination, returning the paths
+/// of the files that were linked, relative to the destination.
+fn link_tree(src: &Path, dst: &Path) -> Result<Vec<String>, SnapshotError> {
+    let mut files = Vec::new();
+    let entries = WalkDir::new(src)
+        .min_depth(1)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .flat_map(|entry| entry.ok());
+
+    for entry in entries {
+        let relative = entry.path().strip_prefix(src).unwrap();
+        let target = dst.join(relative);
+        let result = if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)
+        } else {
+            files.push(relative.to_string_lossy().into_owned());
+            target.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::hard_link(entry.path(), &target))
+        };
+
+        result.map_err(|why| SnapshotError::Link { src: entry.path().to_path_buf(), dst: target, why })?;
+    }
+
+    Ok(files)
+}
+
+/// The paths of every file within the directory, relative to the directory.
+fn relative_files(directory: &Path) -> Vec<String> {
+    WalkDir::new(directory)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .flat_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            entry.path().strip_prefix(directory).ok().map(|path| path.to_string_lossy().into_owned())
+        })
+        .collect()
+}
@@ -0,0 +1,416 @@
+use checksum::{checksums, Checksums};
+use config::Config;
+use debian::{deb822, read_index, Control, INDEX_EXTENSIONS};
+use misc;
+use rayon::prelude::*;
+use signer;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use super::generate::{pool_versions, publish_limit, select_versions};
+use super::pool::{is_archive, is_source};
+use walkdir::WalkDir;
+
+/// The fields of the `Release` file which list the checksums of each index.
+const RELEASE_DIGESTS: &[&str] = &["MD5Sum", "SHA1", "SHA256", "SHA512"];
+
+/// The fields of a `Packages` paragraph which hold the checksums of its archive.
+const PACKAGE_DIGESTS: &[&str] = &["MD5sum", "SHA1", "SHA256", "SHA512"];
+
+/// The outcome of one of the checks which were performed on the suite.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub failures: Vec<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, failures: Vec<String>) -> Self {
+        Check { name, passed: failures.is_empty(), failures }
+    }
+}
+
+/// The outcome of every check which was performed on the suite.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub suite: String,
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+/// A file which the dists expect to exist, along with its size and checksums.
+struct Expected {
+    path: PathBuf,
+    size: Option<u64>,
+    digests: Vec<(&'static str, String)>,
+}
+
+/// Checks that the published dists of the suite are consistent with themselves and the pool.
+///
+/// - every file listed in the `Release` file exists with the size and checksums it claims
+/// - `InRelease` and `Release.gpg` are signed by each configured key
+/// - every archive in each `Packages` index, and every file in each `Sources` index, exists
+///   within the pool with the size and checksums that the index claims
+/// - no archive in the pool of the suite is left out of the indexes, besides the older
+///   versions which are not published because of `publish_versions`
+pub fn verify(config: &Config) -> Report {
+    let signer = signer::from_config(config);
+
+    let repo = Path::new("repo");
+    let dists = repo.join("dists").join(&config.archive);
+    let pool = repo.join("pool").join(&config.archive);
+
+    let mut checks = Vec::new();
+    let release = match misc::read_to_string(dists.join("Release")) {
+        Ok(release) => deb822::parse(&release).into_iter().next().unwrap_or_default(),
+        Err(why) => {
+            checks.push(Check::new("release", vec![format!("Release: {}", why)]));
+            return report(config, checks);
+        }
+    };
+
+    let indexes = release_expectations(&dists, &release);
+    checks.push(Check::new("release", check_files(&indexes)));
+
+    checks.push(Check::new("signatures", {
+        let release = dists.join("Release");
+        let in_release = dists.join("InRelease");
+        let release_gpg = dists.join("Release.gpg");
+        match signer {
+            Ok(signer) => {
+                let (inrelease, release_res) = rayon::join(
+                    || signer.verify_clearsigned(&in_release),
+                    || signer.verify_detached(&release, &release_gpg)
+                );
+
+                let mut failures = Vec::new();
+                if let Err(why) = inrelease {
+                    failures.push(format!("InRelease: {}", why));
+                } else if !signed_text_matches(&release, &in_release) {
+                    failures.push("InRelease: the signed text does not match Release".to_owned());
+                }
+
+                if let Err(why) = release_res {
+                    failures.push(format!("Release.gpg: {}", why));
+                }
+
+                failures
+            }
+            Err(why) => vec![format!("failed to initialize the signer: {}", why)]
+        }
+    }));
+
+    let mut referenced = HashSet::new();
+    let (packages, sources) = rayon::join(
+        || pool_expectations(&dists, &indexes, "Packages", package_files),
+        || pool_expectations(&dists, &indexes, "Sources", source_files)
+    );
+
+    for (name, expectations) in vec![("packages", packages), ("sources", sources)] {
+        checks.push(Check::new(name, match expectations {
+            Ok(expectations) => {
+                let failures = check_files(&expectations);
+                referenced.extend(expectations.into_iter().map(|expected| expected.path));
+                failures
+            }
+            Err(failures) => failures
+        }));
+    }
+
+    match unpublished(config, &pool) {
+        Ok(unpublished) => referenced.extend(unpublished),
+        Err(why) => checks.push(Check::new("publish_versions", vec![format!("{}: {}", display(&pool), why)])),
+    }
+
+    checks.push(Check::new("unreferenced", {
+        WalkDir::new(&pool)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .flat_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| is_archive(entry.path()) || is_source(entry.path()))
+            .filter(|entry| !referenced.contains(entry.path()))
+            .map(|entry| format!("{}: not listed by any index", display(entry.path())))
+            .collect()
+    }));
+
+    report(config, checks)
+}
+
+/// The archives of each binary pool of the suite which are deliberately left out of the indexes,
+/// as newer versions of their packages are published instead.
+fn unpublished(config: &Config, pool: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut unpublished = Vec::new();
+    if !pool.exists() {
+        return Ok(unpublished);
+    }
+
+    for component in pool.read_dir()? {
+        let component = component?.path();
+        if !component.is_dir() {
+            continue
+        }
+
+        for binary in component.read_dir()? {
+            let binary = binary?;
+            let arch = match binary.file_name().to_str() {
+                Some(name) if name.starts_with("binary-") => name[7..].to_owned(),
+                _ => continue
+            };
+
+            let archives = pool_versions(&binary.path(), &arch);
+            unpublished.extend(select_versions(archives, |name, newest| publish_limit(config, name, newest))?.1);
+        }
+    }
+
+    Ok(unpublished)
+}
+
+fn report(config: &Config, checks: Vec<Check>) -> Report {
+    Report {
+        suite: config.archive.clone(),
+        passed: checks.iter().all(|check| check.passed),
+        checks,
+    }
+}
+
+/// Paths in the report are relative to `repo/`, as they are in the indexes.
+fn display(path: &Path) -> String {
+    path.strip_prefix("repo").unwrap_or(path).display().to_string()
+}
+
+/// The files listed by each checksum field of the `Release` file.
+fn release_expectations(dists: &Path, release: &Control) -> Vec<Expected> {
+    let mut files: BTreeMap<&str, Expected> = BTreeMap::new();
+    for &field in RELEASE_DIGESTS {
+        let entries = match release.get(field) {
+            Some(entries) => entries,
+            None => continue
+        };
+
+        for line in entries.lines() {
+            let mut fields = line.split_whitespace();
+            if let (Some(digest), Some(size), Some(path)) = (fields.next(), fields.next(), fields.next()) {
+                let expected = files.entry(path).or_insert_with(|| Expected {
+                    path: dists.join(path),
+                    size: size.parse().ok(),
+                    digests: Vec::new(),
+                });
+
+                expected.digests.push((field, digest.to_owned()));
+            }
+        }
+    }
+
+    files.into_iter().map(|(_, expected)| expected).collect()
+}
+
+/// Reads each index of the given name which is listed in the `Release` file, and collects the
+/// files of the pool that it refers to.
+fn pool_expectations(
+    dists: &Path,
+    indexes: &[Expected],
+    name: &str,
+    files: fn(&Control) -> Result<Vec<Expected>, String>
+) -> Result<Vec<Expected>, Vec<String>> {
+    let mut directories = indexes.iter()
+        .filter(|index| {
+            index.path.file_name()
+                .and_then(|file| file.to_str())
+                .map_or(false, |file| INDEX_EXTENSIONS.iter().any(|ext| file == [name, ext].concat()))
+        })
+        .filter_map(|index| index.path.parent())
+        .collect::<Vec<&Path>>();
+
+    directories.sort();
+    directories.dedup();
+
+    let mut expectations = Vec::new();
+    let mut failures = Vec::new();
+    for directory in directories {
+        let index = match read_index(directory, name) {
+            Ok(index) => index,
+            Err(why) => {
+                failures.push(format!("{}: {}", display(&directory.join(name)), why));
+                continue
+            }
+        };
+
+        let index_name = directory.strip_prefix(dists).unwrap_or(directory).join(name);
+        for paragraph in deb822::parse(&index) {
+            match files(&paragraph) {
+                Ok(files) => expectations.extend(files),
+                Err(why) => failures.push(format!("{}: {}", index_name.display(), why)),
+            }
+        }
+    }
+
+    if failures.is_empty() { Ok(expectations) } else { Err(failures) }
+}
+
+/// The archive of a paragraph within a `Packages` index.
+fn package_files(paragraph: &Control) -> Result<Vec<Expected>, String> {
+    let filename = paragraph.get("Filename")
+        .ok_or_else(|| "a package does not have a Filename field".to_owned())?;
+
+    Ok(vec![Expected {
+        path: Path::new("repo").join(filename),
+        size: paragraph.get("Size").and_then(|size| size.parse().ok()),
+        digests: PACKAGE_DIGESTS.iter()
+            .filter_map(|&field| paragraph.get(field).map(|digest| (field, digest.clone())))
+            .collect(),
+    }])
+}
+
+/// The files of a paragraph within a `Sources` index.
+fn source_files(paragraph: &Control) -> Result<Vec<Expected>, String> {
+    let directory = paragraph.get("Directory")
+        .ok_or_else(|| "a source does not have a Directory field".to_owned())?;
+    let entries = paragraph.get("Checksums-Sha256")
+        .ok_or_else(|| format!("{} does not have a Checksums-Sha256 field", directory))?;
+
+    let files = entries.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(digest), Some(size), Some(name)) => Some(Expected {
+                    path: Path::new("repo").join(directory).join(name),
+                    size: size.parse().ok(),
+                    digests: vec![("SHA256", digest.to_owned())],
+                }),
+                _ => None
+            }
+        })
+        .collect();
+
+    Ok(files)
+}
+
+/// Checks the size and checksums of each file in parallel, returning a description of each
+/// file that did not match.
+fn check_files(files: &[Expected]) -> Vec<String> {
+    files.par_iter()
+        .filter_map(|expected| {
+            let path = display(&expected.path);
+            let metadata = match expected.path.metadata() {
+                Ok(metadata) => metadata,
+                Err(why) => return Some(format!("{}: {}", path, why)),
+            };
+
+            if let Some(size) = expected.size {
+                if metadata.len() != size {
+                    return Some(format!("{}: {} bytes, but {} were expected", path, metadata.len(), size));
+                }
+            }
+
+            let actual = match checksums(&expected.path) {
+                Ok(checksums) => checksums,
+                Err(why) => return Some(format!("{}: {}", path, why)),
+            };
+
+            let mismatched = expected.digests.iter()
+                .filter(|&&(field, ref digest)| digest_of(&actual, field) != digest.as_str())
+                .map(|&(field, _)| field)
+                .collect::<Vec<&str>>();
+
+            if mismatched.is_empty() {
+                None
+            } else {
+                Some(format!("{}: {} checksum does not match", path, mismatched.join(", ")))
+            }
+        })
+        .collect()
+}
+
+fn digest_of<'a>(checksums: &'a Checksums, field: &str) -> &'a str {
+    match field {
+        "MD5Sum" | "MD5sum" => &checksums.md5sum,
+        "SHA1" => &checksums.sha1,
+        "SHA512" => &checksums.sha512,
+        _ => &checksums.sha256,
+    }
+}
+
+/// Whether the text that was clearsigned is the text of the `Release` file.
+fn signed_text_matches(release: &Path, in_release: &Path) -> bool {
+    match (misc::read_to_string(release), misc::read_to_string(in_release)) {
+        (Ok(release), Ok(in_release)) => {
+            deb822::strip_signature(&in_release).trim_end() == release.trim_end()
+        }
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile;
+
+    fn control(fields: &[(&str, &str)]) -> Control {
+        fields.iter().map(|&(key, value)| (key.to_owned(), value.to_owned())).collect()
+    }
+
+    #[test]
+    fn release_files() {
+        let release = control(&[
+            ("MD5Sum", "\n aaaa 10 main/binary-amd64/Packages\n bbbb 20 main/source/Sources.xz"),
+            ("SHA256", "\n cccc 10 main/binary-amd64/Packages\n dddd 20 main/source/Sources.xz"),
+        ]);
+
+        let expected = release_expectations(Path::new("repo/dists/bionic"), &release);
+        assert_eq!(expected.len(), 2);
+        assert_eq!(expected[0].path, Path::new("repo/dists/bionic/main/binary-amd64/Packages"));
+        assert_eq!(expected[0].size, Some(10));
+        assert_eq!(expected[0].digests, vec![("MD5Sum", "aaaa".to_owned()), ("SHA256", "cccc".to_owned())]);
+        assert_eq!(expected[1].path, Path::new("repo/dists/bionic/main/source/Sources.xz"));
+        assert_eq!(expected[1].size, Some(20));
+    }
+
+    #[test]
+    fn source_paragraph_files() {
+        let paragraph = control(&[
+            ("Directory", "pool/bionic/main/source/f/foo"),
+            ("Checksums-Sha256", "\n eeee 100 foo_1.0.dsc\n ffff 2000 foo_1.0.tar.xz"),
+        ]);
+
+        let files = source_files(&paragraph).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, Path::new("repo/pool/bionic/main/source/f/foo/foo_1.0.dsc"));
+        assert_eq!(files[1].size, Some(2000));
+        assert_eq!(files[1].digests, vec![("SHA256", "ffff".to_owned())]);
+
+        assert!(source_files(&control(&[("Directory", "pool/bionic/main/source/f/foo")])).is_err());
+    }
+
+    #[test]
+    fn file_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dists = dir.path().join("dists/bionic");
+        fs::create_dir_all(dists.join("main/binary-amd64")).unwrap();
+        fs::write(dists.join("main/binary-amd64/Packages"), "Package: foo\n").unwrap();
+        let actual = checksums(&dists.join("main/binary-amd64/Packages")).unwrap();
+
+        let release = control(&[
+            ("SHA256", &format!(
+                "\n {0} 13 main/binary-amd64/Packages\n {0} 12 main/binary-amd64/Packages.xz",
+                actual.sha256
+            )),
+            ("MD5Sum", &format!("\n {} 13 main/binary-amd64/Packages", actual.md5sum)),
+        ]);
+
+        let failures = check_files(&release_expectations(&dists, &release));
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].contains("Packages.xz"));
+
+        let release = control(&[("SHA1", "\n 0000 13 main/binary-amd64/Packages")]);
+        let failures = check_files(&release_expectations(&dists, &release));
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].ends_with("SHA1 checksum does not match"));
+
+        let release = control(&[("SHA1", &format!("\n {} 14 main/binary-amd64/Packages", actual.sha1))]);
+        let failures = check_files(&release_expectations(&dists, &release));
+        assert!(failures[0].ends_with("13 bytes, but 14 were expected"));
+    }
+}
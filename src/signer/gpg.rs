@@ -0,0 +1,155 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use super::Signer;
+
+/// Signs via the `gpg` command, with every key given.
+pub struct Gpg {
+    homedir: Option<PathBuf>,
+    keys: Vec<String>,
+}
+
+impl Gpg {
+    pub fn new(homedir: Option<PathBuf>, keys: Vec<String>) -> Self {
+        Gpg { homedir, keys }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("gpg");
+        if let Some(ref homedir) = self.homedir {
+            command.arg("--homedir").arg(homedir);
+        }
+
+        command.args(&["--batch", "--yes"]);
+        command
+    }
+
+    fn sign(&self, mode: &[&str], release: &Path, out: &Path) -> io::Result<()> {
+        let mut command = self.command();
+        command.args(&["--digest-algo", "sha512"]);
+        for key in &self.keys {
+            command.arg("--local-user").arg(key);
+        }
+
+        let status = command.args(mode).arg("-o").arg(out).arg(release).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("gpg failed to sign {}: {}", release.display(), status)
+            ))
+        }
+    }
+
+    /// Verifies the signature, via `gpg --verify`. Detached signatures require the signed data.
+    ///
+    /// A good signature from any key of the keyring is not enough: every configured key must
+    /// have made a valid signature, as reported by the `VALIDSIG` status lines of gpg.
+    fn verify(&self, signature: &Path, data: Option<&Path>) -> io::Result<()> {
+        info!("verifying signature of {}", signature.display());
+        let mut command = self.command();
+        command.args(&["--status-fd", "1", "--verify"]).arg(signature);
+        if let Some(data) = data {
+            command.arg(data);
+        }
+
+        let output = command.stderr(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("signature of {} could not be verified", signature.display())
+            ));
+        }
+
+        let status = String::from_utf8_lossy(&output.stdout);
+        let signers = valid_signers(&status);
+        for key in &self.keys {
+            let fingerprints = self.fingerprints(key)?;
+            if !signers.iter().any(|signer| fingerprints.iter().any(|fpr| fpr == signer)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not signed by {}", signature.display(), key)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The fingerprints of the key, and of its subkeys, which may be given by any name that
+    /// gpg accepts, such as an email, key ID, or fingerprint.
+    fn fingerprints(&self, key: &str) -> io::Result<Vec<String>> {
+        let output = self.command()
+            .args(&["--with-colons", "--fixed-list-mode", "--list-keys"])
+            .arg(key)
+            .stderr(Stdio::null())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not within the keyring", key)
+            ));
+        }
+
+        let fingerprints = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| line.starts_with("fpr:"))
+            .filter_map(|line| line.split(':').nth(9))
+            .map(String::from)
+            .collect();
+
+        Ok(fingerprints)
+    }
+}
+
+/// The fingerprints of the signing key, and of its primary key, of each `VALIDSIG` status line.
+fn valid_signers(status: &str) -> Vec<&str> {
+    let mut signers = Vec::new();
+    for line in status.lines().filter(|line| line.starts_with("[GNUPG:] VALIDSIG ")) {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        signers.extend(fields.get(2));
+        signers.extend(fields.get(11));
+    }
+
+    signers
+}
+
+impl Signer for Gpg {
+    fn clearsign(&self, release: &Path, out: &Path) -> io::Result<()> {
+        info!("generating {} via gpg --clearsign", out.display());
+        self.sign(&["--clearsign"], release, out)?;
+        self.verify(out, None)
+    }
+
+    fn detach_sign(&self, release: &Path, out: &Path) -> io::Result<()> {
+        info!("generating {} via gpg --detach-sign", out.display());
+        self.sign(&["--armor", "--detach-sign"], release, out)?;
+        self.verify(out, Some(release))
+    }
+
+    fn verify_clearsigned(&self, signed: &Path) -> io::Result<()> {
+        self.verify(signed, None)
+    }
+
+    fn verify_detached(&self, release: &Path, signature: &Path) -> io::Result<()> {
+        self.verify(signature, Some(release))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validsig_status() {
+        let status = "[GNUPG:] NEWSIG\n\
+            [GNUPG:] GOODSIG 1234ABCD Repo <repo@example.com>\n\
+            [GNUPG:] VALIDSIG SUBKEYFPR 2018-10-01 1538352000 0 4 0 1 10 01 PRIMARYFPR\n\
+            [GNUPG:] NEWSIG\n\
+            [GNUPG:] BADSIG 5678EF00 Other <other@example.com>\n";
+
+        assert_eq!(valid_signers(status), vec!["SUBKEYFPR", "PRIMARYFPR"]);
+    }
+}
@@ -0,0 +1,48 @@
+mod gpg;
+mod native;
+
+pub use self::gpg::Gpg;
+pub use self::native::Native;
+
+use config::{Config, Signing};
+use std::{env, io};
+use std::path::Path;
+
+/// Generates the `InRelease` and `Release.gpg` files from the `Release` file of a suite.
+///
+/// Each signature is verified after it has been written, so that a broken key will never
+/// publish a signature that APT is unable to verify.
+pub trait Signer: Send + Sync {
+    /// Writes a clearsigned copy of the release file, which is published as `InRelease`.
+    fn clearsign(&self, release: &Path, out: &Path) -> io::Result<()>;
+
+    /// Writes an armored, detached signature of the release file, which is published as `Release.gpg`.
+    fn detach_sign(&self, release: &Path, out: &Path) -> io::Result<()>;
+
+    /// Verifies a clearsigned release file, such as a published `InRelease`.
+    fn verify_clearsigned(&self, signed: &Path) -> io::Result<()>;
+
+    /// Verifies a detached signature of the release file, such as a published `Release.gpg`.
+    fn verify_detached(&self, release: &Path, signature: &Path) -> io::Result<()>;
+}
+
+/// Constructs the signer which the suite is configured to use.
+///
+/// Paths within the config are relative to the root of the project.
+pub fn from_config(config: &Config) -> io::Result<Box<Signer>> {
+    let root = env::current_dir()?;
+
+    let signer: Box<Signer> = match config.signing {
+        None => Box::new(Gpg::new(None, vec![config.email.clone()])),
+        Some(Signing::Gpg { ref homedir, ref keys }) => {
+            let keys = if keys.is_empty() { vec![config.email.clone()] } else { keys.clone() };
+            Box::new(Gpg::new(homedir.as_ref().map(|homedir| root.join(homedir)), keys))
+        }
+        Some(Signing::Native { ref keys }) => {
+            let keys = keys.iter().map(|key| root.join(key)).collect::<Vec<_>>();
+            Box::new(Native::new(&keys)?)
+        }
+    };
+
+    Ok(signer)
+}
@@ -0,0 +1,189 @@
+use chrono::{SubsecRound, Utc};
+use misc;
+use pgp::armor::{self, BlockType};
+use pgp::composed::{CleartextSignedMessage, Deserializable, SignedSecretKey, StandaloneSignature};
+use pgp::crypto::hash::HashAlgorithm;
+use pgp::errors::Result as PgpResult;
+use pgp::packet::{Signature, SignatureConfig, SignatureType, SignatureVersion, Subpacket};
+use pgp::ser::Serialize;
+use pgp::types::KeyTrait;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use super::Signer;
+
+/// Signs with a native OpenPGP implementation, using armored secret keys which are not protected
+/// by a passphrase.
+pub struct Native {
+    keys: Vec<(PathBuf, SignedSecretKey)>,
+}
+
+impl Native {
+    pub fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the native signer requires at least one secret key"
+            ));
+        }
+
+        let keys = paths.iter()
+            .map(|path| {
+                let (key, _) = SignedSecretKey::from_armor_single(File::open(path)?)
+                    .map_err(|why| pgp_error(path, why))?;
+                key.verify().map_err(|why| pgp_error(path, why))?;
+                Ok((path.clone(), key))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Native { keys })
+    }
+
+    /// Signs the data with each key, and verifies each signature against the key that made it.
+    fn signatures(&self, typ: SignatureType, data: &[u8]) -> io::Result<Vec<Signature>> {
+        self.keys.iter()
+            .map(|&(ref path, ref key)| {
+                let signature = SignatureConfig::new_v4(
+                    SignatureVersion::V4,
+                    typ,
+                    key.algorithm(),
+                    HashAlgorithm::SHA2_512,
+                    vec![Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0))],
+                    vec![Subpacket::Issuer(key.key_id())],
+                ).sign(key, String::new, data).map_err(|why| pgp_error(path, why))?;
+
+                signature.verify(key, data).map_err(|why| pgp_error(path, why))?;
+                Ok(signature)
+            })
+            .collect()
+    }
+
+    /// Ensures that the clearsigned message can be parsed, and verified by each key.
+    fn verify_armored(&self, armored: &str, path: &Path) -> io::Result<()> {
+        let (parsed, _) = CleartextSignedMessage::from_string(armored)
+            .map_err(|why| pgp_error(path, why))?;
+        for &(ref key_path, ref key) in &self.keys {
+            parsed.verify(key).map_err(|why| pgp_error(key_path, why))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Signer for Native {
+    fn clearsign(&self, release: &Path, out: &Path) -> io::Result<()> {
+        info!("generating {} via the native signer", out.display());
+        let text = misc::read_to_string(release)?;
+
+        let message = CleartextSignedMessage::new_many(&text, |text| {
+            self.signatures(SignatureType::Text, text.as_bytes())
+                .map_err(|why| pgp::errors::Error::Message(why.to_string()))
+        }).map_err(|why| pgp_error(release, why))?;
+
+        let armored = message.to_armored_string(None).map_err(|why| pgp_error(out, why))?;
+
+        self.verify_armored(&armored, out)?;
+        fs::write(out, armored)
+    }
+
+    fn detach_sign(&self, release: &Path, out: &Path) -> io::Result<()> {
+        info!("generating {} via the native signer", out.display());
+        let data = fs::read(release)?;
+
+        // The signatures of every key are armored together, as gpg does with many local users.
+        let signatures = self.signatures(SignatureType::Binary, &data)?
+            .into_iter()
+            .map(StandaloneSignature::new)
+            .collect();
+
+        let mut armored = Vec::new();
+        armor::write(&Signatures(signatures), BlockType::Signature, &mut armored, None)
+            .map_err(|why| pgp_error(out, why))?;
+
+        fs::write(out, armored)?;
+
+        // A signature which can not be verified is removed, so that it is never published.
+        self.verify_detached(release, out).map_err(|why| {
+            let _ = fs::remove_file(out);
+            why
+        })
+    }
+
+    fn verify_clearsigned(&self, signed: &Path) -> io::Result<()> {
+        info!("verifying signature of {}", signed.display());
+        self.verify_armored(&misc::read_to_string(signed)?, signed)
+    }
+
+    fn verify_detached(&self, release: &Path, signature: &Path) -> io::Result<()> {
+        info!("verifying signature of {}", signature.display());
+        let data = fs::read(release)?;
+        let (signatures, _) = StandaloneSignature::from_armor_many(File::open(signature)?)
+            .map_err(|why| pgp_error(signature, why))?;
+        let signatures = signatures.collect::<Result<Vec<_>, _>>()
+            .map_err(|why| pgp_error(signature, why))?;
+
+        // Each key must have made one of the signatures.
+        for &(ref path, ref key) in &self.keys {
+            if !signatures.iter().any(|signature| signature.verify(key, &data).is_ok()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} was not signed by {}", signature.display(), path.display())
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The packets of many detached signatures, which are written one after another.
+struct Signatures(Vec<StandaloneSignature>);
+
+impl Serialize for Signatures {
+    fn to_writer<W: io::Write>(&self, writer: &mut W) -> PgpResult<()> {
+        for signature in &self.0 {
+            signature.to_writer(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn pgp_error<E: Display>(path: &Path, why: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("OpenPGP error with {}: {}", path.display(), why))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pgp::composed::{KeyType, SecretKeyParamsBuilder};
+    use tempfile;
+
+    #[test]
+    fn detached_round_trip() {
+        let key = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_sign(true)
+            .primary_user_id("Repo <repo@example.com>".into())
+            .build()
+            .unwrap()
+            .generate()
+            .unwrap()
+            .sign(String::new)
+            .unwrap();
+
+        let signer = Native { keys: vec![(PathBuf::from("repo.asc"), key)] };
+
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("Release");
+        let signature = dir.path().join("Release.gpg");
+        fs::write(&release, "Origin: repo\nSuite: bionic\n").unwrap();
+
+        signer.detach_sign(&release, &signature).unwrap();
+        signer.verify_detached(&release, &signature).unwrap();
+
+        fs::write(&release, "Origin: repo\nSuite: cosmic\n").unwrap();
+        assert!(signer.verify_detached(&release, &signature).is_err());
+    }
+}